@@ -0,0 +1,85 @@
+//! Gateway exposing child devices discovered after the agent is started, e.g. Zigbee/BLE devices
+//! behind a Linux hub. Discovery is simulated by adding a new switch every 30 seconds.
+
+use anyhow::Result;
+use examples::{connect_wifi, initializse_logger};
+use rainmaker::components::persistent_storage::NvsPartition;
+use rainmaker::components::wifi::WifiMgr;
+use rainmaker::{
+    device::{Device, DeviceType, ParamWriteResult},
+    node::Node,
+    param::{Param, ParamValue},
+    RainmakerBuilder,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+const MAX_CHILD_DEVICES: usize = 3;
+
+fn create_child_device(device_name: &str) -> Device {
+    let mut child_dev = Device::new(device_name, DeviceType::Switch);
+
+    let mut power_param = Param::new_power("Power", false);
+    power_param.set_persist(true);
+
+    child_dev.add_param(power_param);
+    child_dev.set_primary_param("Power");
+
+    let name = device_name.to_owned();
+    child_dev.register_callback(Box::new(move |params: HashMap<String, ParamValue>| {
+        log::info!("Received update for {}: {:?}", name, params);
+        ParamWriteResult::accept_all(params)
+    }));
+
+    child_dev
+}
+
+fn main() -> Result<()> {
+    initializse_logger();
+
+    let factory_partition = NvsPartition::new("fctry")?;
+    let builder = RainmakerBuilder::new(factory_partition)?;
+    let mut node = Node::new(builder.node_id().to_string());
+    node.set_info(rainmaker::node::Info {
+        name: "Gateway Example Node".to_string(),
+        fw_version: "v1.0".to_string(),
+    });
+
+    let mut rmaker = builder.node(node).build()?;
+
+    // Declare it here since we want wifi to be connected after connect_wifi returns
+    let wifi_arc_mutex = Arc::new(Mutex::new(WifiMgr::new()?));
+    connect_wifi(&rmaker, wifi_arc_mutex.clone())?;
+
+    log::info!("WiFi connected successfully");
+
+    rmaker.start()?;
+
+    log::info!("Rainmaker agent is started");
+
+    let node = rmaker.get_node();
+    for index in 0..MAX_CHILD_DEVICES {
+        std::thread::sleep(std::time::Duration::from_secs(30));
+
+        let device_name = format!("Child {}", index);
+        log::info!("Discovered {}", device_name);
+        node.lock()
+            .unwrap()
+            .add_device(create_child_device(&device_name));
+    }
+
+    // children can be renamed, e.g. once their location is known, and removed when they leave
+    // the network
+    std::thread::sleep(std::time::Duration::from_secs(30));
+    node.lock()
+        .unwrap()
+        .rename_device("Child 1", "Bedroom Child")?;
+    node.lock().unwrap().remove_device("Child 0");
+
+    // Inorder to prevent rmaker from drop
+    loop {
+        std::thread::sleep(std::time::Duration::from_secs(5));
+    }
+}
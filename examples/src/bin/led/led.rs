@@ -2,14 +2,12 @@ use anyhow::Result;
 use examples::{connect_wifi, initializse_logger};
 use rainmaker::components::persistent_storage::NvsPartition;
 use rainmaker::components::wifi::WifiMgr;
-use rainmaker::factory;
 use rainmaker::{
-    device::{Device, DeviceType},
+    device::{Device, DeviceType, ParamWriteResult},
     node::Node,
-    param::Param,
-    Rainmaker,
+    param::{Param, ParamValue},
+    RainmakerBuilder,
 };
-use serde_json::Value;
 use std::{
     collections::HashMap,
     sync::{Arc, LazyLock, Mutex},
@@ -114,35 +112,33 @@ fn init_led_device() -> Device {
     led_device
 }
 
-fn led_cb(params: HashMap<String, Value>) {
+fn led_cb(params: HashMap<String, ParamValue>) -> ParamWriteResult {
     log::info!("Received update: {:?}", params);
 
     let mut current_values = LED_VALUES.lock().unwrap();
 
+    // values are already validated against the param types and bounds
     for param in params.iter() {
-        match param.0.as_str() {
-            "Power" => current_values.0 = param.1.as_bool().unwrap(),
-            "Hue" => current_values.1 = param.1.as_u64().unwrap() as u32,
-            "Saturation" => current_values.2 = param.1.as_u64().unwrap() as u32,
-            "Brightness" => current_values.3 = param.1.as_u64().unwrap() as u32,
+        match (param.0.as_str(), param.1) {
+            ("Power", ParamValue::Bool(v)) => current_values.0 = *v,
+            ("Hue", ParamValue::Integer(v)) => current_values.1 = *v as u32,
+            ("Saturation", ParamValue::Integer(v)) => current_values.2 = *v as u32,
+            ("Brightness", ParamValue::Integer(v)) => current_values.3 = *v as u32,
             _ => {}
         }
     }
 
     #[cfg(target_os = "espidf")]
     esp::update_led_state(&current_values);
-    rainmaker::report_params(DEVICE_NAME, params);
+    ParamWriteResult::accept_all(params)
 }
 
 pub fn main() -> Result<()> {
     initializse_logger();
 
     let factory_partition = NvsPartition::new("fctry")?;
-    // should be done before Rainmaker::init()
-    factory::init(factory_partition)?;
-
-    let rmaker = Rainmaker::init()?;
-    let mut node = Node::new(rmaker.get_node_id().to_string());
+    let builder = RainmakerBuilder::new(factory_partition)?;
+    let mut node = Node::new(builder.node_id().to_string());
     node.set_info(rainmaker::node::Info {
         name: "LED Example Node".to_string(),
         fw_version: "v1.0".to_string(),
@@ -154,16 +150,17 @@ pub fn main() -> Result<()> {
         esp::set_driver(peripherals.pins.gpio8, peripherals.rmt.channel0);
     }
 
+    let led_device = init_led_device();
+    node.add_device(led_device);
+
+    let mut rmaker = builder.node(node).build()?;
+
     // Declare it here since we want wifi to be connected after connect_wifi returns
     let wifi_arc_mutex = Arc::new(Mutex::new(WifiMgr::new()?));
-    connect_wifi(rmaker, wifi_arc_mutex.clone())?;
+    connect_wifi(&rmaker, wifi_arc_mutex.clone())?;
 
     log::info!("WiFi connected successfully");
 
-    let led_device = init_led_device();
-    node.add_device(led_device);
-
-    rmaker.register_node(node);
     rmaker.start()?;
 
     log::info!("Rainmaker agent is started");
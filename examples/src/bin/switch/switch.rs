@@ -3,13 +3,12 @@ use examples::{connect_wifi, initializse_logger};
 use rainmaker::components::persistent_storage::NvsPartition;
 use rainmaker::components::wifi::WifiMgr;
 use rainmaker::{
-    device::{Device, DeviceType},
-    factory,
+    device::{Device, DeviceType, ParamWriteResult},
     node::Node,
-    param::Param,
-    Rainmaker,
+    ota::OtaConfig,
+    param::{Param, ParamValue},
+    RainmakerBuilder,
 };
-use serde_json::Value;
 use std::{
     collections::HashMap,
     sync::{Arc, Mutex},
@@ -26,21 +25,18 @@ fn create_switch_device(device_name: &str) -> Device {
     switch_dev
 }
 
-fn switch_cb(params: HashMap<String, Value>) {
+fn switch_cb(params: HashMap<String, ParamValue>) -> ParamWriteResult {
     log::info!("Received update: {:?}", params);
-    log::info!("Reporting: {:?}", params);
-    rainmaker::report_params("Switch", params);
+    // accepted values are reported by the agent
+    ParamWriteResult::accept_all(params)
 }
 
 fn main() -> Result<()> {
     initializse_logger();
 
     let factory_partition = NvsPartition::new("fctry")?;
-    // factory partition initialization should be performed before Rainmaker::init()
-    factory::init(factory_partition)?;
-
-    let rmaker = Rainmaker::init()?;
-    let mut node = Node::new(rmaker.get_node_id().to_string());
+    let builder = RainmakerBuilder::new(factory_partition)?;
+    let mut node = Node::new(builder.node_id().to_string());
     node.set_info(rainmaker::node::Info {
         name: "Switch Example Node".to_string(),
         fw_version: "v1.0".to_string(),
@@ -48,16 +44,16 @@ fn main() -> Result<()> {
 
     let mut switch_device = create_switch_device("Switch");
     switch_device.register_callback(Box::new(switch_cb));
+    node.add_device(switch_device);
+
+    let mut rmaker = builder.node(node).ota(OtaConfig::default()).build()?;
 
     // Declare it here since we want wifi to be connected after connect_wifi returns
     let wifi_arc_mutex = Arc::new(Mutex::new(WifiMgr::new()?));
-    connect_wifi(rmaker, wifi_arc_mutex.clone())?;
+    connect_wifi(&rmaker, wifi_arc_mutex.clone())?;
 
     log::info!("WiFi connected successfully");
 
-    node.add_device(switch_device);
-
-    rmaker.register_node(node);
     rmaker.start()?;
 
     log::info!("Rainmaker agent is started");
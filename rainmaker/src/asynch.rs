@@ -0,0 +1,158 @@
+//! Asynchronous API of the RainMaker agent.
+//!
+//! Requires the `async` feature. [AsyncRainmaker] wraps a [Rainmaker] agent and exposes its
+//! operations as futures and streams which do not depend on any particular executor, so they can
+//! be used with tokio, async-std or an embedded executor alike.
+//!
+//! - [`start`](AsyncRainmaker::start) resolves once the agent is connected to the MQTT broker.
+//! - [`report_params`](AsyncRainmaker::report_params) resolves once the values are sent to the
+//!   MQTT broker.
+//! - [`param_writes`](AsyncRainmaker::param_writes) returns a [Stream] of the values written to
+//!   the node.
+//!
+//! Example:
+//! ```rust
+//! let mut rmaker = AsyncRainmaker::new(builder.node(node).build()?);
+//! rmaker.start().await?;
+//!
+//! let mut writes = rmaker.param_writes();
+//! while let Some(write) = writes.next().await {
+//!     log::info!("{}: {:?}", write.device, write.params);
+//!     rmaker.report_params("Sensor", read_sensor()).await?;
+//! }
+//! ```
+//!
+//! [Stream]: futures_core::Stream
+
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use futures_channel::{mpsc, oneshot};
+use futures_core::Stream;
+
+use crate::{error::RmakerError, node::Node, param::ParamValue, Rainmaker};
+
+/// Values written to a device(or service) of the node.
+#[derive(Debug, Clone)]
+pub struct ParamWrite {
+    pub device: String,
+    pub params: HashMap<String, ParamValue>,
+}
+
+/// Stream of [ParamWrite]s returned by [`AsyncRainmaker::param_writes`].
+///
+/// Ends once the node is dropped.
+#[derive(Debug)]
+pub struct ParamWrites {
+    rx: mpsc::UnboundedReceiver<ParamWrite>,
+}
+
+impl Stream for ParamWrites {
+    type Item = ParamWrite;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.rx).poll_next(cx)
+    }
+}
+
+/// Asynchronous wrapper around the [Rainmaker] agent.
+#[derive(Debug)]
+pub struct AsyncRainmaker {
+    rmaker: Rainmaker,
+}
+
+impl AsyncRainmaker {
+    pub fn new(rmaker: Rainmaker) -> Self {
+        Self { rmaker }
+    }
+
+    /// Returns the wrapped agent.
+    pub fn inner(&self) -> &Rainmaker {
+        &self.rmaker
+    }
+
+    /// Returns Node ID.
+    pub fn get_node_id(&self) -> &str {
+        self.rmaker.get_node_id()
+    }
+
+    /// Returns the node managed by the agent.
+    pub fn get_node(&self) -> Arc<Mutex<Node>> {
+        self.rmaker.get_node()
+    }
+
+    /// Starts the agent, see [`Rainmaker::start`].
+    ///
+    /// Resolves once the agent is connected to the MQTT broker and the node configuration is
+    /// published.
+    pub async fn start(&mut self) -> Result<(), RmakerError> {
+        self.rmaker.start()?;
+        self.connected().await
+    }
+
+    /// Stops the agent, see [`Rainmaker::stop`].
+    pub async fn stop(&mut self) -> Result<(), RmakerError> {
+        self.rmaker.stop()
+    }
+
+    /// Reports parameters values of a device to the RainMaker cloud, see
+    /// [`Rainmaker::report_params`].
+    ///
+    /// Values are stored in the node right away. The future resolves once the values are handed
+    /// over to the MQTT client while connected, which then takes care of delivering them with
+    /// QoS 1. If the agent is offline, this waits for the connection to be restored. Before the
+    /// agent is started, values are only stored and the future resolves right away. They are
+    /// reported along with the initial values on start.
+    pub async fn report_params(
+        &self,
+        device_name: &str,
+        params: HashMap<String, ParamValue>,
+    ) -> Result<(), RmakerError> {
+        self.rmaker.report_params(device_name, params)?;
+        if !self.rmaker.is_started() {
+            return Ok(());
+        }
+        // messages queued while offline are flushed before the waiters are notified
+        self.connected().await
+    }
+
+    /// Returns a stream of the values written to the node from the RainMaker cloud, over local
+    /// control or by schedules.
+    ///
+    /// Only the values accepted by the device callbacks are streamed, i.e. the ones that were
+    /// applied to the node and reported back. Devices without a callback accept all the valid
+    /// values, so the stream can be used instead of callbacks for applying them.
+    ///
+    /// Each call returns an independent stream which receives the writes made after it was
+    /// created.
+    pub fn param_writes(&self) -> ParamWrites {
+        let (tx, rx) = mpsc::unbounded();
+        self.rmaker
+            .get_node()
+            .lock()
+            .unwrap()
+            .add_write_listener(Box::new(move |device, params| {
+                let write = ParamWrite {
+                    device: device.to_owned(),
+                    params: params.clone(),
+                };
+                // listener is removed once the stream is dropped
+                tx.unbounded_send(write).is_ok()
+            }));
+
+        ParamWrites { rx }
+    }
+
+    async fn connected(&self) -> Result<(), RmakerError> {
+        let (tx, rx) = oneshot::channel();
+        self.rmaker.mqtt.on_connected(move || {
+            let _ = tx.send(());
+        });
+        // sender is dropped if the agent is stopped before connecting
+        rx.await.map_err(|_| RmakerError::NotStarted)
+    }
+}
@@ -0,0 +1,273 @@
+//! Assisted claiming.
+//!
+//! Claiming is the process of obtaining the X509 certificate used by the node for connecting to
+//! the RainMaker cloud. With assisted claiming, this is done during Wi-Fi provisioning, with the
+//! phone application relaying the messages between the node and the claiming service, so that the
+//! nodes don't have to be claimed individually using the CLI while manufacturing.
+//!
+//! The claiming is performed over the `rmaker_claim` protocomm endpoint, same as the C SDK:
+//! 1. Start: node generates a key pair and sends its MAC address and platform.
+//! 2. Init: phone sends the response of the claiming service and the node replies with a CSR.
+//! 3. Verify: phone sends the certificate issued by the claiming service, which is stored in the
+//!    factory partition along with the key and the Node ID.
+//!
+//! Large messages are transferred in chunks. Node ID of an assisted-claimed node is its MAC
+//! address, so the node can be created before the claiming is performed.
+//!
+//! This module is available with the `assisted-claim` feature.
+//!
+//! Example:
+//! ```rust
+//! let builder = RainmakerBuilder::with_assisted_claiming(factory_partition, wifi_mac)?;
+//! let mut node = Node::new(builder.node_id().to_string());
+//! let mut rmaker = builder.node(node).build()?;
+//!
+//! rmaker.reg_assisted_claim_ep(&mut prov_mgr);
+//! rmaker.reg_user_mapping_ep(&mut prov_mgr);
+//! prov_mgr.start()?;
+//! prov_mgr.wait_for_provisioning();
+//!
+//! rmaker.start()?;
+//! ```
+
+use std::sync::Mutex;
+
+use quick_protobuf::{MessageWrite, Writer};
+use rainmaker_components::persistent_storage::NvsPartition;
+use rcgen::{CertificateParams, DistinguishedName, DnType, KeyPair};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{factory, proto::esp_rmaker_claim::*};
+
+// fits in a single BLE write along with the protobuf framing
+const CLAIM_CHUNK_SIZE: usize = 200;
+// upper limit on the certificate payload received in chunks
+const CLAIM_MAX_PAYLOAD_SIZE: usize = 4096;
+
+#[cfg(target_os = "espidf")]
+const CLAIM_PLATFORM: &str = "esp32";
+#[cfg(target_os = "linux")]
+const CLAIM_PLATFORM: &str = "linux";
+
+#[derive(Deserialize)]
+struct ClaimInitResponse {
+    auth_id: String,
+}
+
+#[derive(Deserialize)]
+struct ClaimVerifyResponse {
+    certificate: String,
+}
+
+#[derive(Default)]
+struct ClaimState {
+    key_pair: Option<KeyPair>,
+    // payload sent to the phone in chunks
+    response: Vec<u8>,
+    // payload received from the phone in chunks
+    request: Vec<u8>,
+}
+
+/// State of the assisted claiming performed over the `rmaker_claim` endpoint.
+pub(crate) struct AssistedClaim {
+    node_id: String,
+    factory_partition: NvsPartition,
+    state: Mutex<ClaimState>,
+}
+
+impl AssistedClaim {
+    pub(crate) fn new(node_id: &str, factory_partition: NvsPartition) -> Self {
+        Self {
+            node_id: node_id.to_owned(),
+            factory_partition,
+            state: Mutex::new(ClaimState::default()),
+        }
+    }
+
+    /// Handles a request received on the `rmaker_claim` endpoint and returns the encoded response.
+    pub(crate) fn handle_request(&self, data: &[u8]) -> Vec<u8> {
+        let (msg, response) = match RMakerClaimPayload::try_from(data) {
+            Ok(req) => {
+                let msg = response_type(req.msg);
+                let buf = match req.payload {
+                    mod_RMakerClaimPayload::OneOfpayload::cmdPayload(buf) => buf,
+                    _ => PayloadBuf::default(),
+                };
+                (msg, self.handle_cmd(req.msg, buf))
+            }
+            Err(e) => {
+                log::error!("invalid claim request: {}", e);
+                (
+                    RMakerClaimMsgType::TypeRespClaimAbort,
+                    Err(RMakerClaimStatus::InvalidParam),
+                )
+            }
+        };
+
+        let resp = match response {
+            Ok(buf) => RespPayload {
+                Status: RMakerClaimStatus::Success,
+                Buf: buf,
+            },
+            Err(status) => RespPayload {
+                Status: status,
+                Buf: None,
+            },
+        };
+        let res_proto = RMakerClaimPayload {
+            msg,
+            payload: mod_RMakerClaimPayload::OneOfpayload::respPayload(resp),
+        };
+
+        let mut out_vec = vec![];
+        let mut writer = Writer::new(&mut out_vec);
+        if let Err(e) = res_proto.write_message(&mut writer) {
+            log::error!("could not encode claim response: {}", e);
+            return vec![];
+        }
+
+        out_vec
+    }
+
+    fn handle_cmd(
+        &self,
+        msg: RMakerClaimMsgType,
+        buf: PayloadBuf,
+    ) -> Result<Option<PayloadBuf>, RMakerClaimStatus> {
+        let mut state = self.state.lock().unwrap();
+        match msg {
+            RMakerClaimMsgType::TypeCmdClaimStart => {
+                log::info!("starting assisted claiming");
+                let key_pair = KeyPair::generate().map_err(|e| {
+                    log::error!("could not generate key pair: {}", e);
+                    RMakerClaimStatus::Fail
+                })?;
+                *state = ClaimState {
+                    key_pair: Some(key_pair),
+                    response: json!({
+                        "mac_addr": self.node_id,
+                        "platform": CLAIM_PLATFORM,
+                    })
+                    .to_string()
+                    .into_bytes(),
+                    request: vec![],
+                };
+                Ok(Some(state.response_chunk(0)?))
+            }
+            RMakerClaimMsgType::TypeCmdClaimInit => {
+                // remaining chunks of the CSR are requested with an empty payload
+                if buf.Payload.is_empty() {
+                    return Ok(Some(state.response_chunk(buf.Offset as usize)?));
+                }
+                let init: ClaimInitResponse =
+                    serde_json::from_slice(&buf.Payload).map_err(|e| {
+                        log::error!("invalid claim init payload: {}", e);
+                        RMakerClaimStatus::InvalidParam
+                    })?;
+                let key_pair = state
+                    .key_pair
+                    .as_ref()
+                    .ok_or(RMakerClaimStatus::InvalidState)?;
+                let csr = generate_csr(&self.node_id, key_pair).map_err(|e| {
+                    log::error!("could not generate CSR: {}", e);
+                    RMakerClaimStatus::Fail
+                })?;
+                state.response = json!({
+                    "auth_id": init.auth_id,
+                    "mac_addr": self.node_id,
+                    "csr": csr,
+                })
+                .to_string()
+                .into_bytes();
+                Ok(Some(state.response_chunk(0)?))
+            }
+            RMakerClaimMsgType::TypeCmdClaimVerify => {
+                if state.key_pair.is_none() {
+                    return Err(RMakerClaimStatus::InvalidState);
+                }
+                if !state.add_request_chunk(&buf)? {
+                    return Ok(None);
+                }
+                let verify: ClaimVerifyResponse =
+                    serde_json::from_slice(&state.request).map_err(|e| {
+                        log::error!("invalid claim verify payload: {}", e);
+                        RMakerClaimStatus::InvalidParam
+                    })?;
+                let key = state
+                    .key_pair
+                    .take()
+                    .map(|key_pair| key_pair.serialize_pem())
+                    .ok_or(RMakerClaimStatus::InvalidState)?;
+                factory::set_credentials(
+                    &self.factory_partition,
+                    &self.node_id,
+                    verify.certificate.as_bytes(),
+                    key.as_bytes(),
+                )
+                .map_err(|e| {
+                    log::error!("could not store node credentials: {}", e);
+                    RMakerClaimStatus::Fail
+                })?;
+                *state = ClaimState::default();
+                log::info!("assisted claiming successful");
+                Ok(None)
+            }
+            RMakerClaimMsgType::TypeCmdClaimAbort => {
+                log::warn!("assisted claiming aborted");
+                *state = ClaimState::default();
+                Ok(None)
+            }
+            _ => Err(RMakerClaimStatus::InvalidParam),
+        }
+    }
+}
+
+impl ClaimState {
+    fn response_chunk(&self, offset: usize) -> Result<PayloadBuf, RMakerClaimStatus> {
+        let chunk = self
+            .response
+            .get(offset..)
+            .ok_or(RMakerClaimStatus::InvalidParam)?;
+        Ok(PayloadBuf {
+            Offset: offset as u32,
+            Payload: chunk[..chunk.len().min(CLAIM_CHUNK_SIZE)].to_vec(),
+            TotalLen: self.response.len() as u32,
+        })
+    }
+
+    /// Adds a chunk of the request payload. Returns true once the complete payload is received.
+    fn add_request_chunk(&mut self, buf: &PayloadBuf) -> Result<bool, RMakerClaimStatus> {
+        let total_len = buf.TotalLen as usize;
+        if total_len > CLAIM_MAX_PAYLOAD_SIZE || buf.Offset as usize != self.request.len() {
+            return Err(RMakerClaimStatus::InvalidParam);
+        }
+        self.request.extend_from_slice(&buf.Payload);
+        if self.request.len() > total_len {
+            return Err(RMakerClaimStatus::InvalidParam);
+        }
+
+        Ok(self.request.len() == total_len)
+    }
+}
+
+fn response_type(msg: RMakerClaimMsgType) -> RMakerClaimMsgType {
+    match msg {
+        RMakerClaimMsgType::TypeCmdClaimStart => RMakerClaimMsgType::TypeRespClaimStart,
+        RMakerClaimMsgType::TypeCmdClaimInit => RMakerClaimMsgType::TypeRespClaimInit,
+        RMakerClaimMsgType::TypeCmdClaimVerify => RMakerClaimMsgType::TypeRespClaimVerify,
+        _ => RMakerClaimMsgType::TypeRespClaimAbort,
+    }
+}
+
+fn generate_csr(node_id: &str, key_pair: &KeyPair) -> Result<String, rcgen::Error> {
+    let mut params = CertificateParams::default();
+    params.distinguished_name = DistinguishedName::new();
+    params.distinguished_name.push(DnType::CommonName, node_id);
+    params.serialize_request(key_pair)?.pem()
+}
+
+/// Returns Node ID used for the assisted claiming, i.e. the MAC address in uppercase hex.
+pub(crate) fn node_id_from_mac(mac: [u8; 6]) -> String {
+    mac.iter().map(|b| format!("{:02X}", b)).collect()
+}
@@ -0,0 +1,326 @@
+//! Command-response framework.
+//!
+//! Commands are requests sent by the RainMaker cloud to the node on `node/<node_id>/to-node`
+//! topic, separate from parameters. Each request carries a request id, the role of the user
+//! sending it, a command id and optional data. The response is published on
+//! `node/<node_id>/from-node` topic with the same request id, so that the result of the command can
+//! be fetched later by the user. This is useful for operations that do not fit the parameter
+//! semantics, e.g. running diagnostics.
+//!
+//! Requests and responses are encoded as TLVs(1 byte type, 2 bytes little endian length, value),
+//! same as the C SDK. The data is passed to the handler as it is and is usually JSON.
+//!
+//! Handlers are registered using [`Rainmaker::register_command`] along with the roles allowed to
+//! execute the command. Requests from other roles are rejected by the agent. Each request is
+//! handled in a separate thread, so handlers may take a while to complete.
+//!
+//! Example:
+//! ```rust
+//! rmaker.register_command(
+//!     DIAGNOSTICS_CMD,
+//!     HashSet::from([UserRole::SuperAdmin, UserRole::PrimaryUser]),
+//!     Box::new(|req| {
+//!         let report = run_diagnostics();
+//!         CommandResponse::success(report.into_bytes())
+//!     }),
+//! )?;
+//! ```
+//!
+//! [`Rainmaker::register_command`]: crate::Rainmaker::register_command
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+    thread,
+};
+
+use crate::{
+    constants::*,
+    error::{RmakerCommandError, RmakerMqttError},
+    rmaker_mqtt::RmakerMqtt,
+};
+
+const TLV_TYPE_REQ_ID: u8 = 1;
+const TLV_TYPE_USER_ROLE: u8 = 2;
+const TLV_TYPE_STATUS: u8 = 3;
+const TLV_TYPE_CMD: u8 = 5;
+const TLV_TYPE_DATA: u8 = 6;
+
+pub(crate) type CommandCbType =
+    Box<dyn Fn(&CommandRequest) -> CommandResponse + Send + Sync + 'static>;
+type SharedCommandCb = Arc<CommandCbType>;
+
+/// Role of the user sending a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UserRole {
+    SuperAdmin,
+    PrimaryUser,
+    SecondaryUser,
+}
+
+impl UserRole {
+    fn from_u8(role: u8) -> Option<Self> {
+        match role {
+            1 => Some(Self::SuperAdmin),
+            2 => Some(Self::PrimaryUser),
+            4 => Some(Self::SecondaryUser),
+            _ => None,
+        }
+    }
+}
+
+/// Status of a command execution reported in the response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandStatus {
+    Success = 0,
+    Failed = 1,
+    InvalidCommand = 2,
+    AuthFailed = 3,
+    NotFound = 4,
+}
+
+/// Command request received from the cloud.
+#[derive(Debug, Clone)]
+pub struct CommandRequest {
+    pub req_id: String,
+    pub role: UserRole,
+    pub cmd: u16,
+    pub data: Vec<u8>,
+}
+
+/// Result of a command returned by the handler.
+///
+/// `data` can be at most 65535 bytes long. A response with longer data is sent as
+/// [`CommandStatus::Failed`] without any data.
+#[derive(Debug, Clone)]
+pub struct CommandResponse {
+    status: CommandStatus,
+    data: Vec<u8>,
+}
+
+impl CommandResponse {
+    /// Command is executed successfully. `data` is sent back to the cloud.
+    pub fn success(data: Vec<u8>) -> Self {
+        Self {
+            status: CommandStatus::Success,
+            data,
+        }
+    }
+
+    /// Command could not be executed. `data` can describe the failure.
+    pub fn failed(data: Vec<u8>) -> Self {
+        Self {
+            status: CommandStatus::Failed,
+            data,
+        }
+    }
+
+    fn with_status(status: CommandStatus) -> Self {
+        Self {
+            status,
+            data: vec![],
+        }
+    }
+}
+
+struct RegisteredCommand {
+    roles: HashSet<UserRole>,
+    handler: SharedCommandCb,
+}
+
+/// Commands registered with an agent.
+pub(crate) struct Commands {
+    node_id: String,
+    mqtt: RmakerMqtt,
+    commands: RwLock<HashMap<u16, RegisteredCommand>>,
+}
+
+impl Commands {
+    pub(crate) fn new(node_id: &str, mqtt: RmakerMqtt) -> Arc<Self> {
+        Arc::new(Self {
+            node_id: node_id.to_owned(),
+            mqtt,
+            commands: RwLock::new(HashMap::new()),
+        })
+    }
+
+    pub(crate) fn register(
+        &self,
+        cmd: u16,
+        roles: HashSet<UserRole>,
+        handler: CommandCbType,
+    ) -> Result<(), RmakerCommandError> {
+        let mut commands = self.commands.write().unwrap();
+        if commands.contains_key(&cmd) {
+            return Err(RmakerCommandError::AlreadyRegistered(cmd));
+        }
+        commands.insert(
+            cmd,
+            RegisteredCommand {
+                roles,
+                handler: Arc::new(handler),
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Subscribes to `to-node` topic. Called by the agent once MQTT is initialized.
+    pub(crate) fn start(self: &Arc<Self>) -> Result<(), RmakerMqttError> {
+        let to_node_topic = format!("node/{}/{}", self.node_id, TO_NODE_TOPIC_SUFFIX);
+        let commands = Arc::downgrade(self);
+        self.mqtt.subscribe(&to_node_topic, move |msg| {
+            if let Some(commands) = commands.upgrade() {
+                commands.handle_request(&msg.payload);
+            }
+        })?;
+
+        Ok(())
+    }
+
+    fn handle_request(&self, payload: &[u8]) {
+        let tlvs = match parse_tlvs(payload) {
+            Some(tlvs) => tlvs,
+            None => {
+                log::error!("invalid command request payload");
+                return;
+            }
+        };
+        // response cannot be matched to the request without an id
+        let req_id = match tlvs
+            .get(&TLV_TYPE_REQ_ID)
+            .and_then(|id| String::from_utf8(id.to_vec()).ok())
+        {
+            Some(req_id) => req_id,
+            None => {
+                log::error!("command request without request id");
+                return;
+            }
+        };
+        let cmd = tlvs
+            .get(&TLV_TYPE_CMD)
+            .and_then(|cmd| <[u8; 2]>::try_from(*cmd).ok())
+            .map(u16::from_le_bytes);
+        let role = tlvs
+            .get(&TLV_TYPE_USER_ROLE)
+            .and_then(|role| role.first())
+            .and_then(|role| UserRole::from_u8(*role));
+
+        let (cmd, role) = match (cmd, role) {
+            (Some(cmd), Some(role)) => (cmd, role),
+            _ => {
+                log::error!("invalid command request {}", req_id);
+                let response = CommandResponse::with_status(CommandStatus::InvalidCommand);
+                self.publish_response(&req_id, cmd.unwrap_or(0), &response);
+                return;
+            }
+        };
+
+        let handler = match self.get_handler(cmd, role) {
+            Ok(handler) => handler,
+            Err(status) => {
+                log::warn!("rejecting command {} from {:?}: {:?}", cmd, role, status);
+                let response = CommandResponse::with_status(status);
+                self.publish_response(&req_id, cmd, &response);
+                return;
+            }
+        };
+
+        let request = CommandRequest {
+            req_id,
+            role,
+            cmd,
+            data: tlvs
+                .get(&TLV_TYPE_DATA)
+                .map(|data| data.to_vec())
+                .unwrap_or_default(),
+        };
+        log::info!("executing command {} for request {}", cmd, request.req_id);
+        // handlers may take a while, so they are not executed in the MQTT event task
+        let req_id = request.req_id.clone();
+        let response_topic = self.response_topic();
+        let mqtt = self.mqtt.clone();
+        let spawned = thread::Builder::new()
+            .name("rmaker_cmd".to_string())
+            .spawn(move || {
+                let response = handler(&request);
+                publish_response(&mqtt, &response_topic, &request.req_id, cmd, &response);
+            });
+        if spawned.is_err() {
+            log::error!("could not spawn command task");
+            let response = CommandResponse::with_status(CommandStatus::Failed);
+            self.publish_response(&req_id, cmd, &response);
+        }
+    }
+
+    fn get_handler(&self, cmd: u16, role: UserRole) -> Result<SharedCommandCb, CommandStatus> {
+        let commands = self.commands.read().unwrap();
+        let command = commands.get(&cmd).ok_or(CommandStatus::NotFound)?;
+        if !command.roles.contains(&role) {
+            return Err(CommandStatus::AuthFailed);
+        }
+
+        Ok(command.handler.clone())
+    }
+
+    fn response_topic(&self) -> String {
+        format!("node/{}/{}", self.node_id, FROM_NODE_TOPIC_SUFFIX)
+    }
+
+    fn publish_response(&self, req_id: &str, cmd: u16, response: &CommandResponse) {
+        publish_response(&self.mqtt, &self.response_topic(), req_id, cmd, response);
+    }
+}
+
+fn publish_response(
+    mqtt: &RmakerMqtt,
+    topic: &str,
+    req_id: &str,
+    cmd: u16,
+    response: &CommandResponse,
+) {
+    // data longer than a TLV can hold cannot be sent
+    let (status, data) = if response.data.len() > u16::MAX as usize {
+        log::error!(
+            "response data for request {} is too long: {} bytes",
+            req_id,
+            response.data.len()
+        );
+        (CommandStatus::Failed, &[][..])
+    } else {
+        (response.status, &response.data[..])
+    };
+
+    let mut payload = vec![];
+    write_tlv(&mut payload, TLV_TYPE_REQ_ID, req_id.as_bytes());
+    write_tlv(&mut payload, TLV_TYPE_STATUS, &[status as u8]);
+    write_tlv(&mut payload, TLV_TYPE_CMD, &cmd.to_le_bytes());
+    if !data.is_empty() {
+        write_tlv(&mut payload, TLV_TYPE_DATA, data);
+    }
+
+    if let Err(e) = mqtt.publish(topic, payload) {
+        log::error!("could not publish response for request {}: {}", req_id, e);
+    }
+}
+
+// value must be at most u16::MAX bytes long, req_id is parsed from a TLV so it always fits
+fn write_tlv(buff: &mut Vec<u8>, tlv_type: u8, value: &[u8]) {
+    let len = u16::try_from(value.len()).expect("TLV value too long");
+    buff.push(tlv_type);
+    buff.extend_from_slice(&len.to_le_bytes());
+    buff.extend_from_slice(value);
+}
+
+fn parse_tlvs(mut bytes: &[u8]) -> Option<HashMap<u8, &[u8]>> {
+    let mut tlvs = HashMap::new();
+    while !bytes.is_empty() {
+        let (&tlv_type, rest) = bytes.split_first()?;
+        let (len, rest) = rest.split_first_chunk::<2>()?;
+        let len = u16::from_le_bytes(*len) as usize;
+        tlvs.insert(tlv_type, rest.get(..len)?);
+        bytes = &rest[len..];
+    }
+
+    Some(tlvs)
+}
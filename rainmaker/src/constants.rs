@@ -1,5 +1,17 @@
 pub const USER_MAPPING_TOPIC_SUFFIX: &str = "user/mapping";
 pub const NODE_CONFIG_TOPIC_SUFFIX: &str = "config";
+pub const NODE_CONFIG_VERSION: &str = "2020-03-20";
 pub const NODE_PARAMS_LOCAL_INIT_TOPIC_SUFFIX: &str = "params/local/init";
 pub const NODE_PARAMS_REMOTE_TOPIC_SUFFIX: &str = "params/remote";
 pub const NODE_PARAMS_LOCAL_TOPIC_SUFFIX: &str = "params/local";
+pub const NODE_PARAMS_NOTIFY_TOPIC_SUFFIX: &str = "params/local/notify";
+pub const NODE_ALERT_TOPIC_SUFFIX: &str = "alert";
+pub const ALERT_STR_KEY: &str = "esp.alert.str";
+pub const ALERT_MAX_LEN: usize = 100;
+pub const TO_NODE_TOPIC_SUFFIX: &str = "to-node";
+pub const FROM_NODE_TOPIC_SUFFIX: &str = "from-node";
+pub const OTA_URL_TOPIC_SUFFIX: &str = "otaurl";
+pub const OTA_STATUS_TOPIC_SUFFIX: &str = "otastatus";
+pub const OTA_FETCH_TOPIC_SUFFIX: &str = "otafetch";
+pub const TS_DATA_TOPIC_SUFFIX: &str = "tsdata";
+pub const SIMPLE_TS_DATA_TOPIC_SUFFIX: &str = "simple_tsdata";
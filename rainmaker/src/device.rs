@@ -18,34 +18,97 @@
 //!
 //! A callback needed to be set for every device in order to report updated values of parameters.
 //!
-//! For the reporting purpose, the function [report_params] can be used.
+//! The callback receives typed values([ParamValue]) which are already validated against the data
+//! type, bounds and write permission of the respective parameters. Invalid writes are rejected
+//! and logged by the agent and never reach the callback.
+//!
+//! The callback returns a [ParamWriteResult] describing which values were applied. Accepted values
+//! are stored in the node and reported to the RainMaker cloud by the agent itself, rejected ones
+//! are logged and left unchanged.
 //!
 //! Example for device callback:
 //! ```rust
-//! fn device_callback(params: HashMap<String, Value>){
-//!     /* Write code for logging the received and reported values */
-//!     rainmaker::report_params(device_name: "DeviceName", params);
+//! fn device_callback(params: HashMap<String, ParamValue>) -> ParamWriteResult {
+//!     /* Write code for applying the received values */
+//!     ParamWriteResult::accept_all(params)
 //! }
 //! ```
 //!
+//! Values changed by the device itself(e.g. using a physical button) can be reported using
+//! [report_params].
+//!
 //! The callback created can be associated with device using [register_callback].
 //! ```rust
 //! device.register_callback(Box::new(device_callback));
 //! ```
 //!
 //! [Param]: crate::param::Param
+//! [ParamValue]: crate::param::ParamValue
+//! [ParamWriteResult]: crate::device::ParamWriteResult
 //! [register_callback]: crate::device::Device::register_callback
+//! [report_params]: crate::Rainmaker::report_params
 
-use std::{collections::HashMap, fmt::Debug};
+use std::{collections::HashMap, fmt::Debug, sync::Arc};
 
 use serde::Serialize;
-use serde_json::Value;
 
-use crate::param::Param;
-#[allow(unused)]
-use crate::report_params;
+use crate::param::{Param, ParamValue};
+
+pub(crate) type DeviceCbType =
+    Box<dyn Fn(HashMap<String, ParamValue>) -> ParamWriteResult + Send + Sync + 'static>;
+// callbacks are shared so that they can be executed without holding a lock on the node
+pub(crate) type SharedDeviceCb =
+    Arc<dyn Fn(HashMap<String, ParamValue>) -> ParamWriteResult + Send + Sync + 'static>;
+
+/// Result of applying parameter updates, returned by device callbacks.
+///
+/// A value can be accepted as received, accepted with a modified value(e.g. clamped by the
+/// hardware) or rejected. Parameters not mentioned in the result are treated as rejected.
+#[derive(Debug, Default)]
+pub struct ParamWriteResult {
+    accepted: HashMap<String, ParamValue>,
+    rejected: Vec<String>,
+}
+
+impl ParamWriteResult {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accepts all the received values as they are.
+    pub fn accept_all(params: HashMap<String, ParamValue>) -> Self {
+        Self {
+            accepted: params,
+            rejected: vec![],
+        }
+    }
+
+    /// Accepts a parameter with the value actually applied by the device.
+    pub fn accept(&mut self, param_name: &str, value: ParamValue) -> &mut Self {
+        self.accepted.insert(param_name.to_owned(), value);
+        self
+    }
+
+    /// Rejects the value received for a parameter.
+    pub fn reject(&mut self, param_name: &str) -> &mut Self {
+        self.rejected.push(param_name.to_owned());
+        self
+    }
+
+    /// Returns accepted values.
+    pub fn accepted(&self) -> &HashMap<String, ParamValue> {
+        &self.accepted
+    }
+
+    /// Returns names of rejected parameters.
+    pub fn rejected(&self) -> &[String] {
+        &self.rejected
+    }
 
-pub(crate) type DeviceCbType = Box<dyn Fn(HashMap<String, Value>) + Send + Sync + 'static>;
+    pub(crate) fn into_accepted(self) -> HashMap<String, ParamValue> {
+        self.accepted
+    }
+}
 
 #[derive(Serialize)]
 pub struct Device {
@@ -58,7 +121,7 @@ pub struct Device {
     attributes: HashMap<String, String>,
     params: Vec<Param>,
     #[serde(skip_serializing)]
-    callback: Option<DeviceCbType>,
+    callback: Option<SharedDeviceCb>,
 }
 
 impl Debug for Device {
@@ -92,9 +155,7 @@ impl Device {
     }
 
     pub fn add_attribute(&mut self, name: String, value: String) {
-        self.attributes
-            .insert(name, value)
-            .expect("Failed to add attribute");
+        self.attributes.insert(name, value);
     }
 
     /// This function associates a parameter with the device.
@@ -102,9 +163,20 @@ impl Device {
         self.params.push(param);
     }
 
+    /// Removes a parameter from the device and returns it.
+    ///
+    /// The primary parameter is unset if it is removed.
+    pub fn remove_param(&mut self, param_name: &str) -> Option<Param> {
+        let index = self.params.iter().position(|p| p.name() == param_name)?;
+        if self.primary_param.as_deref() == Some(param_name) {
+            self.primary_param = None;
+        }
+        Some(self.params.remove(index))
+    }
+
     /// This function associates a callback that reports updates values of parameters.
     pub fn register_callback(&mut self, cb: DeviceCbType) {
-        self.callback = Some(Box::new(cb));
+        self.callback = Some(Arc::from(cb));
     }
 
     /// Function for assigning a name to device.
@@ -117,14 +189,25 @@ impl Device {
         &self.params
     }
 
-    pub(crate) fn execute_callback(&self, params: HashMap<String, /* ParamDataType */ Value>) {
-        let cb = if self.callback.is_some() {
-            self.callback.as_ref().unwrap()
-        } else {
-            return;
-        };
+    /// Returns parameter with the given name.
+    pub fn param(&self, param_name: &str) -> Option<&Param> {
+        self.params.iter().find(|p| p.name() == param_name)
+    }
+
+    pub(crate) fn set_name(&mut self, name: &str) {
+        self.name = name.to_owned();
+    }
+
+    pub(crate) fn params_mut(&mut self) -> &mut [Param] {
+        &mut self.params
+    }
+
+    pub(crate) fn param_mut(&mut self, param_name: &str) -> Option<&mut Param> {
+        self.params.iter_mut().find(|p| p.name() == param_name)
+    }
 
-        cb(params);
+    pub(crate) fn callback(&self) -> Option<SharedDeviceCb> {
+        self.callback.clone()
     }
 }
 
@@ -8,6 +8,12 @@ pub enum RmakerMqttError {
     NodeCredentialsNotFound,
     #[error("not initialized")]
     NotInitialized,
+    #[error("invalid topic filter: {0}")]
+    InvalidTopic(String),
+    #[error("no such subscription")]
+    NotSubscribed,
+    #[error("not supported by the transport")]
+    NotSupported,
     #[error("unknown error")]
     OtherError,
 }
@@ -15,22 +21,130 @@ pub enum RmakerMqttError {
 #[derive(Error, Debug)]
 #[non_exhaustive]
 pub enum RmakerError {
-    #[error("already initialized")]
-    AlreadyInitialized,
+    #[error("agent already started")]
+    AlreadyStarted,
+    #[error("agent not started")]
+    NotStarted,
     #[error("MQTT wrapper error")]
     Mqtt(#[from] RmakerMqttError),
     #[error("factory partition error")]
     Factory(#[from] RmakerFactoryError),
+    #[error("OTA error")]
+    Ota(#[from] RmakerOtaError),
+    #[error("node not registered")]
+    NodeNotRegistered,
+    #[error("claim data error: {0}")]
+    ClaimData(String),
+    #[error("invalid payload: {0}")]
+    InvalidPayload(String),
+    #[error("could not report values: {0}")]
+    ReportFailed(String),
+    #[error("parameter not found")]
+    ParamNotFound,
+    #[error("device {0} not found")]
+    DeviceNotFound(String),
+    #[error("device {0} already exists")]
+    DeviceExists(String),
+    #[error("invalid parameter value")]
+    InvalidParamValue,
+    #[error("local control error")]
+    LocalCtrl(#[from] RmakerLocalCtrlError),
+    #[error("schedule error")]
+    Schedule(#[from] RmakerScheduleError),
+    #[error("time service error")]
+    Time(#[from] RmakerTimeError),
+    #[error("system service error")]
+    System(#[from] RmakerSystemError),
+    #[error("command error")]
+    Command(#[from] RmakerCommandError),
+    #[error("could not spawn time series data task")]
+    TsDataTaskSpawnFailed,
 }
 
 #[derive(Error, Debug)]
 pub enum RmakerFactoryError {
-    #[error("already initialized")]
-    AlreadyInitialized,
-    #[error("not initialized")]
-    NotInitialized,
     #[error("partition not found")]
     PartitionNotFound,
     #[error("value read error")]
     ValueReadError,
+    #[error("value write error")]
+    ValueWriteError,
+}
+
+#[derive(Error, Debug)]
+pub enum RmakerOtaError {
+    #[error("not initialized")]
+    NotInitialized,
+    #[error("image download failed: {0}")]
+    DownloadError(String),
+    #[error("image write failed: {0}")]
+    WriteError(String),
+    #[error("image validation failed: {0}")]
+    ImageValidationFailed(String),
+    #[error("rollback error: {0}")]
+    RollbackError(String),
+    #[error("MQTT wrapper error")]
+    Mqtt(#[from] RmakerMqttError),
+}
+
+#[derive(Error, Debug)]
+pub enum RmakerLocalCtrlError {
+    #[error("protocomm error")]
+    ProtocommError,
+    #[error("mDNS error")]
+    MdnsError,
+}
+
+#[derive(Error, Debug)]
+pub enum RmakerScheduleError {
+    #[error("schedule {0} already exists")]
+    AlreadyExists(String),
+    #[error("schedule {0} not found")]
+    NotFound(String),
+    #[error("invalid request for schedule {0}")]
+    InvalidRequest(String),
+    #[error("maximum number of schedules reached")]
+    LimitReached,
+    #[error("schedule {0} does not fit in the storage")]
+    TooLarge(String),
+    #[error("could not store schedules")]
+    StoreFailed,
+    #[error("could not spawn scheduler task")]
+    TaskSpawnFailed,
+}
+
+#[derive(Error, Debug)]
+pub enum RmakerTimeError {
+    #[error("unknown timezone {0}")]
+    UnknownTimezone(String),
+    #[error("SNTP error")]
+    SntpError,
+}
+
+#[derive(Error, Debug)]
+pub enum RmakerSystemError {
+    #[error("another operation is pending")]
+    OperationPending,
+    #[error("factory reset failed: {0}")]
+    FactoryResetFailed(String),
+    #[error("Wi-Fi reset failed: {0}")]
+    WifiResetFailed(String),
+    #[error("could not spawn system task")]
+    TaskSpawnFailed,
+}
+
+#[derive(Error, Debug)]
+pub enum RmakerCommandError {
+    #[error("command {0} already registered")]
+    AlreadyRegistered(u16),
+}
+
+#[derive(Error, Debug)]
+pub enum RmakerParamError {
+    #[error("parameter is not writable")]
+    NotWritable,
+    #[error("invalid value type")]
+    InvalidType,
+    #[error("value out of bounds")]
+    OutOfBounds,
 }
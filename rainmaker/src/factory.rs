@@ -1,45 +1,91 @@
+//! Access to the node credentials stored in the factory(`fctry`) partition.
+//!
+//! The partition is provided to [RainmakerBuilder](crate::RainmakerBuilder) and is owned by the
+//! agent.
+
 use rainmaker_components::persistent_storage::{Nvs, NvsPartition};
-use std::sync::OnceLock;
 
 use crate::error::RmakerFactoryError;
 
-static PARTITION: OnceLock<NvsPartition> = OnceLock::new();
-
-pub fn init(partition: NvsPartition) -> Result<(), RmakerFactoryError> {
-    if PARTITION.get().is_some() {
-        return Err(RmakerFactoryError::AlreadyInitialized);
-    }
+/// Returns the Node ID, which is also the common name of the client certificate.
+pub fn get_node_id(
+    partition: &NvsPartition,
+    buff: &mut [u8],
+) -> Result<String, RmakerFactoryError> {
+    let bytes = get_bytes_factory(partition, "node_id", buff)?;
+    String::from_utf8(bytes).map_err(|_| RmakerFactoryError::ValueReadError)
+}
 
-    // Can't fail since PARTITION is not set
-    let _ = PARTITION.set(partition);
+/// Returns the PEM encoded client certificate used for connecting to the MQTT broker.
+pub fn get_client_cert(
+    partition: &NvsPartition,
+    buff: &mut [u8],
+) -> Result<Vec<u8>, RmakerFactoryError> {
+    get_bytes_factory(partition, "client_cert", buff)
+}
 
-    Ok(())
+/// Returns the PEM encoded private key of the client certificate.
+pub fn get_client_key(
+    partition: &NvsPartition,
+    buff: &mut [u8],
+) -> Result<Vec<u8>, RmakerFactoryError> {
+    get_bytes_factory(partition, "client_key", buff)
 }
 
-pub(crate) fn get_node_id(buff: &mut [u8]) -> Result<String, RmakerFactoryError> {
-    let bytes = get_bytes_factory("node_id", buff)?;
-    // This should not fail if claiming is performed properly
-    Ok(String::from_utf8(bytes).unwrap())
+/// Returns the MQTT host stored in the factory partition, if any.
+pub fn get_mqtt_host(
+    partition: &NvsPartition,
+    buff: &mut [u8],
+) -> Result<String, RmakerFactoryError> {
+    let bytes = get_bytes_factory(partition, "mqtt_host", buff)?;
+    String::from_utf8(bytes).map_err(|_| RmakerFactoryError::ValueReadError)
 }
 
-pub(crate) fn get_client_cert(buff: &mut [u8]) -> Result<Vec<u8>, RmakerFactoryError> {
-    get_bytes_factory("client_cert", buff)
+pub fn get_client_random(
+    partition: &NvsPartition,
+    buff: &mut [u8],
+) -> Result<Vec<u8>, RmakerFactoryError> {
+    get_bytes_factory(partition, "random", buff)
 }
 
-pub(crate) fn get_client_key(buff: &mut [u8]) -> Result<Vec<u8>, RmakerFactoryError> {
-    get_bytes_factory("client_key", buff)
+/// Returns true if the node credentials are stored in the factory partition.
+#[cfg(feature = "assisted-claim")]
+pub(crate) fn is_claimed(partition: &NvsPartition) -> bool {
+    let mut buff = vec![0u8; 2500];
+    ["node_id", "client_cert", "client_key"]
+        .iter()
+        .all(|key| get_bytes_factory(partition, key, &mut buff).is_ok())
 }
 
-pub fn get_client_random(buff: &mut [u8]) -> Result<Vec<u8>, RmakerFactoryError> {
-    get_bytes_factory("random", buff)
+/// Stores the node credentials obtained by claiming in the factory partition.
+#[cfg(feature = "assisted-claim")]
+pub(crate) fn set_credentials(
+    partition: &NvsPartition,
+    node_id: &str,
+    client_cert: &[u8],
+    client_key: &[u8],
+) -> Result<(), RmakerFactoryError> {
+    let mut nvs = Nvs::new(partition.clone(), "rmaker_creds")
+        .map_err(|_| RmakerFactoryError::PartitionNotFound)?;
+    // node_id is stored last, so that a partially claimed node is not treated as claimed
+    for (key, value) in [
+        ("client_cert", client_cert),
+        ("client_key", client_key),
+        ("node_id", node_id.as_bytes()),
+    ] {
+        nvs.set_bytes(key, value)
+            .map_err(|_| RmakerFactoryError::ValueWriteError)?;
+    }
+
+    Ok(())
 }
 
-fn get_bytes_factory(nvs_key: &str, buff: &mut [u8]) -> Result<Vec<u8>, RmakerFactoryError> {
-    let factory_partition = match PARTITION.get() {
-        Some(partition) => partition,
-        None => return Err(RmakerFactoryError::NotInitialized),
-    };
-    let nvs = match Nvs::new(factory_partition.clone(), "rmaker_creds") {
+fn get_bytes_factory(
+    partition: &NvsPartition,
+    nvs_key: &str,
+    buff: &mut [u8],
+) -> Result<Vec<u8>, RmakerFactoryError> {
+    let nvs = match Nvs::new(partition.clone(), "rmaker_creds") {
         Ok(nvs) => nvs,
         Err(_) => return Err(RmakerFactoryError::PartitionNotFound),
     };
@@ -6,58 +6,116 @@
 //!
 //! Full fledged C based ESP RainMaker SDK can be found [here](https://github.com/espressif/esp-rainmaker).
 
+#[cfg(feature = "async")]
+pub mod asynch;
+pub mod command;
 pub mod device;
 pub mod error;
 pub mod factory;
+#[cfg(feature = "local-ctrl")]
+pub mod local_ctrl;
 pub mod node;
+pub mod ota;
 pub mod param;
 pub(crate) mod proto;
+pub mod schedule;
+pub mod service;
+pub mod system;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+pub mod time;
+pub mod transport;
+pub mod ts_data;
 pub(crate) mod utils;
 
+#[cfg(feature = "assisted-claim")]
+mod claim;
 mod constants;
+mod publish_queue;
 mod rmaker_mqtt;
 
+use command::{CommandCbType, Commands, UserRole};
 use constants::*;
+use device::ParamWriteResult;
 use error::RmakerError;
+#[cfg(feature = "local-ctrl")]
+use local_ctrl::LocalCtrlConfig;
 use node::Node;
+use ota::{Ota, OtaConfig};
+use param::ParamValue;
 use proto::esp_rmaker_user_mapping::*;
+pub use publish_queue::{PublishQueueConfig, PublishQueueStats};
 use quick_protobuf::{MessageWrite, Writer};
+use rmaker_mqtt::{ConnectionCbType, RmakerMqtt, TopicCbType};
+pub use rmaker_mqtt::{ConnectionEvent, SubscriptionId};
+use schedule::Scheduler;
+use system::SystemServiceConfig;
+pub use transport::MqttConfig;
+use transport::{MqttTransport, Transport};
+use ts_data::TsDataConfig;
 // expose rainmaker_components crate for use in downstream crates
 pub use rainmaker_components as components;
 use rainmaker_components::{
     mqtt::ReceivedMessage,
+    persistent_storage::NvsPartition,
     wifi_prov::{WiFiProvTransportTrait, WifiProvMgr},
 };
 use serde_json::{json, Value};
 use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex, OnceLock},
-    thread,
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    sync::{mpsc::Sender, Arc, Mutex},
     time::Duration,
 };
 
 #[cfg(target_os = "linux")]
-use rainmaker_components::persistent_storage::{Nvs, NvsPartition};
+use rainmaker_components::persistent_storage::Nvs;
 #[cfg(target_os = "linux")]
 use std::{env, fs, path::Path};
 
 pub(crate) type WrappedInArcMutex<T> = Arc<Mutex<T>>;
 
-/// A struct for RainMaker Agent.
-#[derive(Debug)]
-pub struct Rainmaker {
-    node: Option<Arc<node::Node>>,
+/// Builder for the RainMaker agent.
+///
+/// The builder owns everything required by an agent: factory partition containing the node
+/// credentials, MQTT configuration, the node and the standard services to be enabled. Each call
+/// to [`build`](RainmakerBuilder::build) produces an independent agent, so multiple agents can
+/// live in the same process. Runtime data(persistent parameters, schedules, etc.) is stored in
+/// the `nvs` partition, which is shared by all the agents.
+///
+/// # Example
+/// ```rust
+/// let builder = RainmakerBuilder::new(NvsPartition::new("fctry")?)?;
+/// let mut node = Node::new(builder.node_id().to_string());
+/// node.add_device(device);
+///
+/// let mut rmaker = builder
+///     .node(node)
+///     .ota(OtaConfig::default())
+///     .build()?;
+/// rmaker.start()?;
+/// ```
+pub struct RainmakerBuilder {
+    factory_partition: NvsPartition,
     node_id: String,
+    node: Option<Node>,
+    mqtt_config: MqttConfig,
+    transport: Option<Box<dyn Transport>>,
+    publish_queue_config: PublishQueueConfig,
+    ts_data_config: TsDataConfig,
+    ota_config: Option<OtaConfig>,
+    schedules: bool,
+    time_service: bool,
+    system_service_config: Option<SystemServiceConfig>,
+    #[cfg(feature = "local-ctrl")]
+    local_ctrl_config: Option<LocalCtrlConfig>,
 }
 
-static mut RAINMAKER: OnceLock<Rainmaker> = OnceLock::new();
-
-impl Rainmaker {
-    /// Initializes the RainMaker Agent.
-    ///
-    /// Throws an error if agent is already initialized else returns the mutable reference of Rainmaker.
+impl RainmakerBuilder {
+    /// Creates a builder using the node credentials stored in the provided factory partition.
     ///
-    /// This function panics if node claiming is not performed.
+    /// Returns an error if node claiming is not performed. Nodes can also be claimed during Wi-Fi
+    /// provisioning, see [`RainmakerBuilder::with_assisted_claiming`].
     ///
     /// For claiming process, ensure following steps are performed:
     /// - Install [`esp-rainmaker-cli`](https://rainmaker.espressif.com/docs/cli-setup/) package.
@@ -75,26 +133,255 @@ impl Rainmaker {
     ///             ./rainmaker.py claim --mac <MAC addr> /dev/null
     ///         ```
     ///     3. Set the "RMAKER_CLAIMDATA_PATH" environment variable to the folder containing the Node X509 certificate and key (usually stored at ```/home/<user>/.espressif/rainmaker/claim_data/<acc_id>/<mac_addr>```)
-    pub fn init() -> Result<&'static mut Self, RmakerError> {
+    pub fn new(factory_partition: NvsPartition) -> Result<Self, RmakerError> {
         #[cfg(target_os = "linux")]
-        Self::linux_init_claimdata();
+        linux_init_claimdata(&factory_partition)?;
 
-        if unsafe { RAINMAKER.get().is_some() } {
-            return Err(RmakerError::AlreadyInitialized);
+        let mut buff = [0u8; 32];
+        let node_id = factory::get_node_id(&factory_partition, &mut buff)?;
+
+        Ok(Self::with_node_id(factory_partition, node_id))
+    }
+
+    /// Creates a builder for a node which can be claimed during Wi-Fi provisioning, see
+    /// [`Rainmaker::reg_assisted_claim_ep`].
+    ///
+    /// If the node is already claimed, this is same as [`RainmakerBuilder::new`]. Otherwise the
+    /// Node ID is derived from `mac`(usually the Wi-Fi station MAC address) and the credentials
+    /// are obtained during provisioning, before the agent is started.
+    ///
+    /// Requires the `assisted-claim` feature.
+    #[cfg(feature = "assisted-claim")]
+    pub fn with_assisted_claiming(
+        factory_partition: NvsPartition,
+        mac: [u8; 6],
+    ) -> Result<Self, RmakerError> {
+        if factory::is_claimed(&factory_partition) {
+            return Self::new(factory_partition);
         }
-        unsafe {
-            let mut buff = [0u8; 32];
-            let node_id = factory::get_node_id(&mut buff)?;
-            RAINMAKER
-                .set(Self {
-                    node: None,
-                    node_id,
-                })
-                .unwrap();
+
+        let node_id = claim::node_id_from_mac(mac);
+        log::info!("node not claimed. using {} as Node ID", node_id);
+        Ok(Self::with_node_id(factory_partition, node_id))
+    }
+
+    fn with_node_id(factory_partition: NvsPartition, node_id: String) -> Self {
+        Self {
+            factory_partition,
+            node_id,
+            node: None,
+            mqtt_config: MqttConfig::default(),
+            transport: None,
+            publish_queue_config: PublishQueueConfig::default(),
+            ts_data_config: TsDataConfig::default(),
+            ota_config: None,
+            schedules: false,
+            time_service: false,
+            system_service_config: None,
+            #[cfg(feature = "local-ctrl")]
+            local_ctrl_config: None,
         }
-        Ok(unsafe { RAINMAKER.get_mut().unwrap() })
     }
 
+    /// Returns Node ID read from the factory partition.
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    /// Sets the node to be managed by the agent.
+    ///
+    /// Values of persistent parameters(see [`Param::set_persist`](param::Param::set_persist)) are restored from NVS when the agent is built.
+    pub fn node(mut self, node: Node) -> Self {
+        self.node = Some(node);
+        self
+    }
+
+    /// Sets the MQTT endpoint used for connecting to the RainMaker cloud.
+    ///
+    /// This is required only for private RainMaker deployments or for connecting to a test
+    /// broker. See [MqttConfig] for the defaults.
+    /// # Example
+    /// ```rust
+    /// let builder = builder.mqtt_config(MqttConfig {
+    ///     host: Some("mqtt.example.com".to_string()),
+    ///     server_cert: Some(std::fs::read("ca.crt")?),
+    ///     ..Default::default()
+    /// });
+    /// ```
+    pub fn mqtt_config(mut self, config: MqttConfig) -> Self {
+        self.mqtt_config = config;
+        self
+    }
+
+    /// Sets the [Transport] used for connecting to the RainMaker cloud, instead of the default
+    /// [MqttTransport]. [MqttConfig] is not used in this case.
+    pub fn transport(mut self, transport: Box<dyn Transport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Sets the configuration of the queue holding messages published while offline. See
+    /// [PublishQueueConfig] for the defaults.
+    /// # Example
+    /// ```rust
+    /// let builder = builder.publish_queue(PublishQueueConfig {
+    ///     capacity: 64,
+    ///     persist: true,
+    ///     ..Default::default()
+    /// });
+    /// ```
+    pub fn publish_queue(mut self, config: PublishQueueConfig) -> Self {
+        self.publish_queue_config = config;
+        self
+    }
+
+    /// Sets batching of time series data. See [ts_data] for details.
+    pub fn ts_data(mut self, config: TsDataConfig) -> Self {
+        self.ts_data_config = config;
+        self
+    }
+
+    /// Enables OTA firmware updates using the provided [OtaConfig].
+    pub fn ota(mut self, config: OtaConfig) -> Self {
+        self.ota_config = Some(config);
+        self
+    }
+
+    /// Enables schedules service.
+    ///
+    /// Schedules are stored on the node and executed by the agent once it is started.
+    pub fn schedules(mut self) -> Self {
+        self.schedules = true;
+        self
+    }
+
+    /// Enables time service which allows setting timezone of the node from the phone application.
+    pub fn time_service(mut self) -> Self {
+        self.time_service = true;
+        self
+    }
+
+    /// Enables system service using the provided [SystemServiceConfig].
+    ///
+    /// This allows rebooting and resetting the node remotely.
+    pub fn system_service(mut self, config: SystemServiceConfig) -> Self {
+        self.system_service_config = Some(config);
+        self
+    }
+
+    /// Enables control of the node over local network using the provided [LocalCtrlConfig].
+    ///
+    /// Requires the `local-ctrl` feature.
+    #[cfg(feature = "local-ctrl")]
+    pub fn local_ctrl(mut self, config: LocalCtrlConfig) -> Self {
+        self.local_ctrl_config = Some(config);
+        self
+    }
+
+    /// Builds the agent. Returns an error if no node is set.
+    ///
+    /// Nothing is reported to the cloud until [`Rainmaker::start`] is called.
+    pub fn build(self) -> Result<Rainmaker, RmakerError> {
+        let mut node = self.node.ok_or(RmakerError::NodeNotRegistered)?;
+        let transport = self
+            .transport
+            .unwrap_or_else(|| Box::new(MqttTransport::new(self.mqtt_config)));
+        let mqtt = RmakerMqtt::new(&self.node_id, transport, self.publish_queue_config);
+        node.set_mqtt(mqtt.clone());
+        // batches are reported by a task only if values are held back
+        let ts_data_max_delay =
+            (self.ts_data_config.batch_size > 1).then_some(self.ts_data_config.max_delay);
+        node.set_ts_data_config(self.ts_data_config);
+
+        if self.time_service {
+            node.add_service(time::init());
+        }
+        if let Some(config) = self.system_service_config {
+            node.add_service(system::init(config));
+        }
+        #[cfg(feature = "local-ctrl")]
+        if let Some(config) = &self.local_ctrl_config {
+            node.add_service(local_ctrl::create_local_ctrl_service(config));
+        }
+        let scheduler = self.schedules.then(|| {
+            let scheduler = Scheduler::new();
+            node.add_service(scheduler.service());
+            scheduler
+        });
+
+        let node = Arc::new(Mutex::new(node));
+        let ota = self.ota_config.map(|config| {
+            let ota = Ota::new(&self.node_id, config, mqtt.clone(), Arc::downgrade(&node));
+            if let Some(ota_service) = ota.service() {
+                node.lock().unwrap().add_service(ota_service);
+            }
+            ota
+        });
+        let commands = Commands::new(&self.node_id, mqtt.clone());
+        node.lock().unwrap().restore_persisted_params();
+
+        Ok(Rainmaker {
+            node,
+            node_id: self.node_id,
+            factory_partition: self.factory_partition,
+            mqtt,
+            ota,
+            commands,
+            scheduler,
+            ts_data_max_delay,
+            ts_data_stop_tx: None,
+            time_service: self.time_service,
+            #[cfg(feature = "local-ctrl")]
+            local_ctrl_config: self.local_ctrl_config,
+            #[cfg(feature = "local-ctrl")]
+            local_ctrl: None,
+            started: false,
+        })
+    }
+}
+
+/// A struct for RainMaker Agent.
+///
+/// Created using [RainmakerBuilder]. Dropping a started agent stops it, see [`Rainmaker::stop`].
+pub struct Rainmaker {
+    node: WrappedInArcMutex<Node>,
+    node_id: String,
+    factory_partition: NvsPartition,
+    mqtt: RmakerMqtt,
+    ota: Option<Arc<Ota>>,
+    commands: Arc<Commands>,
+    scheduler: Option<Scheduler>,
+    ts_data_max_delay: Option<Duration>,
+    // dropping the sender stops the time series data task
+    ts_data_stop_tx: Option<Sender<()>>,
+    time_service: bool,
+    #[cfg(feature = "local-ctrl")]
+    local_ctrl_config: Option<LocalCtrlConfig>,
+    #[cfg(feature = "local-ctrl")]
+    local_ctrl: Option<local_ctrl::LocalCtrl>,
+    started: bool,
+}
+
+impl Drop for Rainmaker {
+    fn drop(&mut self) {
+        if self.started {
+            self.shutdown();
+        }
+    }
+}
+
+impl Debug for Rainmaker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Rainmaker")
+            .field("node_id", &self.node_id)
+            .field("node", &self.node)
+            .field("mqtt", &self.mqtt)
+            .field("started", &self.started)
+            .finish()
+    }
+}
+
+impl Rainmaker {
     /// Returns Node ID.
     pub fn get_node_id(&self) -> &str {
         &self.node_id
@@ -103,55 +390,290 @@ impl Rainmaker {
     /// Starts the RainMaker core task which includes connect to RainMaker cloud over MQTT if hasn't been already.
     ///
     /// Reports node configuration and initial values of parameters, subscribe to respective topics and wait for commands.
-    /// # Ensure agent(node) is initialized and WiFi is connected before using this function.
+    ///
+    /// Returns an error if the agent is already started. A stopped agent can be started again.
+    /// # Ensure WiFi is connected before using this function.
     pub fn start(&mut self) -> Result<(), RmakerError> {
+        if self.started {
+            return Err(RmakerError::AlreadyStarted);
+        }
+
+        // whatever was started before the failure is stopped so that start can be retried
+        if let Err(e) = self.start_services() {
+            self.shutdown();
+            return Err(e);
+        }
+        self.started = true;
+
+        Ok(())
+    }
+
+    /// Stops the agent started using [`start`](Rainmaker::start).
+    ///
+    /// Schedules and local control are stopped, messages queued while offline are flushed and
+    /// the agent disconnects from the RainMaker cloud, dropping all the subscriptions. The node
+    /// and the configuration are kept, so the agent can be started again, e.g. on configuration
+    /// reload or before exiting on `SIGTERM`.
+    ///
+    /// Returns an error if the agent is not started.
+    pub fn stop(&mut self) -> Result<(), RmakerError> {
+        if !self.started {
+            return Err(RmakerError::NotStarted);
+        }
+
+        self.shutdown();
+        self.started = false;
+        log::info!("Rainmaker agent stopped");
+
+        Ok(())
+    }
+
+    /// Returns whether the agent is started.
+    pub fn is_started(&self) -> bool {
+        self.started
+    }
+
+    fn start_services(&mut self) -> Result<(), RmakerError> {
         // initialize mqtt if not done already
-        if !rmaker_mqtt::is_mqtt_initialized() {
-            rmaker_mqtt::init_rmaker_mqtt()?;
+        if !self.mqtt.is_initialized() {
+            self.mqtt.init(&self.factory_partition)?;
         }
 
-        let curr_node = &self.node;
         let node_id = self.get_node_id();
-        let node_config_topic = format!("node/{}/{}", node_id, NODE_CONFIG_TOPIC_SUFFIX);
         let params_local_init_topic =
             format!("node/{}/{}", node_id, NODE_PARAMS_LOCAL_INIT_TOPIC_SUFFIX);
         let remote_param_topic = format!("node/{}/{}", node_id, NODE_PARAMS_REMOTE_TOPIC_SUFFIX);
 
-        match curr_node {
-            Some(node) => {
-                let node_config = serde_json::to_string(node.as_ref()).unwrap();
-                log::info!("publishing nodeconfig: {}", node_config);
-                rmaker_mqtt::publish(&node_config_topic, node_config.into())?;
-
-                let init_params = node.get_param_values();
-                let init_params = serde_json::to_string(&init_params).unwrap();
-                log::info!("publishing initial params: {}", init_params);
-                rmaker_mqtt::publish(&params_local_init_topic, init_params.into())?;
-                let node = node.clone();
-                thread::sleep(Duration::from_secs(1)); // wait for connection
-                rmaker_mqtt::subscribe(&remote_param_topic, move |msg| {
-                    remote_params_callback(msg, &node)
-                })?
+        let fw_version = {
+            let node = self.node.lock().unwrap();
+            node.publish_config()?;
+
+            let init_params = node.get_param_values();
+            let init_params = serde_json::to_string(&init_params)
+                .map_err(|e| RmakerError::ReportFailed(e.to_string()))?;
+            log::info!("publishing initial params: {}", init_params);
+            self.mqtt
+                .publish(&params_local_init_topic, init_params.into())?;
+
+            node.info()
+                .map(|info| info.fw_version.to_owned())
+                .unwrap_or_default()
+        };
+
+        #[cfg(feature = "local-ctrl")]
+        if let Some(local_ctrl_config) = &self.local_ctrl_config {
+            self.local_ctrl = Some(local_ctrl::start(
+                local_ctrl_config,
+                &self.node_id,
+                self.node.clone(),
+            )?);
+        }
+
+        // subscriptions are owned by the connection, so they must not keep the node alive
+        let node = Arc::downgrade(&self.node);
+        self.mqtt.subscribe(&remote_param_topic, move |msg| {
+            if let Some(node) = node.upgrade() {
+                remote_params_callback(msg, &node)
             }
-            None => panic!("error while starting: node not registered"),
+        })?;
+        self.commands.start()?;
+
+        if let Some(ota) = &self.ota {
+            ota.start(&fw_version)?;
+        }
+
+        if self.time_service {
+            time::start()?;
+        }
+
+        if let Some(scheduler) = &self.scheduler {
+            scheduler.start(Arc::downgrade(&self.node))?;
+        }
+
+        if let Some(max_delay) = self.ts_data_max_delay {
+            self.ts_data_stop_tx = Some(ts_data::start_flush_task(
+                Arc::downgrade(&self.node),
+                max_delay,
+            )?);
         }
 
         Ok(())
     }
 
-    /// Registers node to agent.
+    // mqtt is stopped last so that values reported while stopping are still flushed
+    fn shutdown(&mut self) {
+        if let Some(scheduler) = &self.scheduler {
+            scheduler.stop();
+        }
+        self.ts_data_stop_tx = None;
+        #[cfg(feature = "local-ctrl")]
+        {
+            self.local_ctrl = None;
+        }
+        if let Err(e) = self.flush_ts_data() {
+            log::error!("could not report pending time series values: {}", e);
+        }
+        self.mqtt.deinit();
+    }
+
+    /// Returns whether the agent is connected to the RainMaker cloud.
+    pub fn is_connected(&self) -> bool {
+        self.mqtt.is_connected()
+    }
+
+    /// Raises an alert which is shown as a push notification on the phones of the users of the
+    /// node, same as `esp_rmaker_raise_alert` of the C SDK.
     ///
-    /// This should be called before the `start()` function.
-    /// # Example
+    /// Messages longer than 100 characters are truncated. Returns an error if the agent is not
+    /// started. Alerts raised while offline are queued like other messages.
+    ///
+    /// Parameters can also raise notifications on change, see
+    /// [`Param::set_notify`](param::Param::set_notify).
+    /// ```rust
+    /// rmaker.raise_alert("Water leak detected in the kitchen")?;
+    /// ```
+    pub fn raise_alert(&self, msg: &str) -> Result<(), RmakerError> {
+        if !self.started {
+            return Err(RmakerError::NotStarted);
+        }
+
+        let msg: String = msg.chars().take(ALERT_MAX_LEN).collect();
+        let payload = json!({ ALERT_STR_KEY: msg });
+        let alert_topic = format!("node/{}/{}", self.node_id, NODE_ALERT_TOPIC_SUFFIX);
+        self.mqtt
+            .publish(&alert_topic, payload.to_string().into_bytes())
+            .map_err(|e| RmakerError::ReportFailed(e.to_string()))
+    }
+
+    /// Reports the time series values held back for batching, see [ts_data].
+    pub fn flush_ts_data(&self) -> Result<(), RmakerError> {
+        self.node.lock().unwrap().flush_ts_data()
+    }
+
+    /// Returns statistics of the queue holding messages published while offline.
+    ///
+    /// Messages dropped because the queue was full are also reported using
+    /// [`ConnectionEvent::PublishFailed`].
+    pub fn publish_queue_stats(&self) -> PublishQueueStats {
+        self.mqtt.publish_queue_stats()
+    }
+
+    /// Registers a callback which is executed on every [ConnectionEvent], e.g. for driving a
+    /// status LED.
+    ///
+    /// Multiple callbacks can be registered and they are kept when the agent is stopped and
+    /// started again. Callbacks are executed from the MQTT event task, so they should return
+    /// quickly.
     /// ```rust
-    /// let rmaker = Rainmaker::init()?;
-    /// let mut node = Node::new(rmaker.get_node_id());
-    /// rmaker.register_node();
-    /// rmaker.start();
+    /// rmaker.register_connection_callback(Box::new(|event| match event {
+    ///     ConnectionEvent::Connected => status_led.set_high(),
+    ///     ConnectionEvent::Disconnected => status_led.set_low(),
+    ///     _ => {}
+    /// }));
     /// ```
+    pub fn register_connection_callback(&self, cb: ConnectionCbType) {
+        self.mqtt.register_event_callback(cb);
+    }
+
+    /// Registers a handler for command `cmd` received on `node/<node_id>/to-node` topic, see
+    /// [command].
+    ///
+    /// Requests from users having a role not in `roles` are rejected without executing the
+    /// handler. The response returned by the handler is published on `node/<node_id>/from-node`
+    /// topic. Handlers are executed in a separate thread, so they can perform long-running
+    /// operations. Commands can be registered before or after the agent is started.
+    ///
+    /// Returns an error if a handler is already registered for `cmd`.
+    /// ```rust
+    /// rmaker.register_command(
+    ///     DIAGNOSTICS_CMD,
+    ///     HashSet::from([UserRole::SuperAdmin]),
+    ///     Box::new(|req| CommandResponse::success(run_diagnostics(&req.data))),
+    /// )?;
+    /// ```
+    pub fn register_command(
+        &self,
+        cmd: u16,
+        roles: HashSet<UserRole>,
+        handler: CommandCbType,
+    ) -> Result<(), RmakerError> {
+        Ok(self.commands.register(cmd, roles, handler)?)
+    }
+
+    /// Registers a handler for the messages received on `topic`, e.g. for custom topics under
+    /// `node/<node_id>/`.
+    ///
+    /// `topic` can be an MQTT topic filter with `+`(single level) and `#`(multiple levels)
+    /// wildcards. Multiple handlers can be registered for the same filter and a message is
+    /// delivered to the handlers of all the matching filters, in the order they were registered.
+    /// Handlers are executed from the MQTT event task, so they should return quickly.
+    ///
+    /// Returns an error if the agent is not started or the filter is invalid. Handlers are
+    /// dropped when the agent is stopped.
+    /// ```rust
+    /// let id = rmaker.subscribe(
+    ///     &format!("node/{}/diag/+", rmaker.get_node_id()),
+    ///     Box::new(|msg| log::info!("{}: {} bytes", msg.topic, msg.payload.len())),
+    /// )?;
+    /// rmaker.unsubscribe(id)?;
+    /// ```
+    pub fn subscribe(&self, topic: &str, cb: TopicCbType) -> Result<SubscriptionId, RmakerError> {
+        if !self.started {
+            return Err(RmakerError::NotStarted);
+        }
+
+        Ok(self.mqtt.subscribe(topic, cb)?)
+    }
+
+    /// Removes a handler registered using [`Rainmaker::subscribe`]. The topic is unsubscribed
+    /// once all of its handlers are removed.
     ///
-    pub fn register_node(&mut self, node: Node) {
-        self.node = Some(node.into());
+    /// The handler is removed even if unsubscribing fails. The default [MqttTransport] cannot
+    /// unsubscribe, so this returns [`RmakerMqttError::NotSupported`](error::RmakerMqttError::NotSupported)
+    /// when the last handler of a topic is removed while connected. Messages on the topic are then
+    /// still received, but ignored, till the next reconnection.
+    pub fn unsubscribe(&self, id: SubscriptionId) -> Result<(), RmakerError> {
+        Ok(self.mqtt.unsubscribe(id)?)
+    }
+
+    /// Returns the node managed by the agent.
+    ///
+    /// The node can be used for updating parameter values after the agent is started, e.g. using
+    /// [`Node::update_param`].
+    pub fn get_node(&self) -> Arc<Mutex<Node>> {
+        self.node.clone()
+    }
+
+    /// Reports parameters values of a device to the RainMaker cloud over MQTT.
+    ///
+    /// Appropriate Device Name and a map of parameters(name: value) must be provided.
+    ///
+    /// Values stored in the node are updated as well, so that the node's model always matches
+    /// what was reported. Values of persistent parameters are also stored to NVS.
+    ///
+    /// Values accepted in device callbacks are reported by the agent itself. This function is meant
+    /// for reporting changes made by the device itself, e.g. using a physical button.
+    ///
+    /// Returns [`RmakerError::DeviceNotFound`] for an unknown device and
+    /// [`RmakerError::ReportFailed`] if the values could not be published.
+    ///
+    /// Example
+    /// ```
+    /// fn on_button_press(rmaker: &Rainmaker, state: bool) -> Result<(), RmakerError>
+    /// {
+    ///     let params = HashMap::from([("Power".to_string(), ParamValue::Bool(state))]);
+    ///     rmaker.report_params("DeviceName", params)
+    /// }
+    /// ```
+    pub fn report_params(
+        &self,
+        device_name: &str,
+        params: HashMap<String, ParamValue>,
+    ) -> Result<(), RmakerError> {
+        self.node
+            .lock()
+            .unwrap()
+            .report_param_values(device_name, &params)
     }
 
     /// Registers the endpoint used for claiming process with `WiFiProvMgr`. This is used for associating a RainMaker node with the user account performing the provisioning.
@@ -159,82 +681,192 @@ impl Rainmaker {
     /// This should be called before `WiFiProvMgr::start()`
     pub fn reg_user_mapping_ep<T: WiFiProvTransportTrait>(&self, prov_mgr: &mut WifiProvMgr<T>) {
         let node_id = self.get_node_id().to_string();
+        let mqtt = self.mqtt.clone();
+        let factory_partition = self.factory_partition.clone();
         prov_mgr.add_endpoint(
             "cloud_user_assoc",
-            Box::new(move |ep, data| -> Vec<u8> { cloud_user_assoc_callback(ep, data, &node_id) }),
+            Box::new(move |ep, data| -> Vec<u8> {
+                cloud_user_assoc_callback(ep, data, &node_id, &mqtt, &factory_partition)
+            }),
         )
     }
 
-    #[cfg(target_os = "linux")]
-    fn linux_init_claimdata() {
-        let fctry_partition = NvsPartition::new("fctry").unwrap();
-        let mut rmaker_namespace = Nvs::new(fctry_partition, "rmaker_creds").unwrap();
+    /// Registers the `rmaker_claim` endpoint used for assisted claiming with `WiFiProvMgr`, see
+    /// [`RainmakerBuilder::with_assisted_claiming`].
+    ///
+    /// The phone application performs the claiming before the user mapping, so this should be
+    /// called along with [`reg_user_mapping_ep`](Rainmaker::reg_user_mapping_ep) before
+    /// `WiFiProvMgr::start()`. The endpoint is not registered if the node is already claimed.
+    ///
+    /// Requires the `assisted-claim` feature.
+    #[cfg(feature = "assisted-claim")]
+    pub fn reg_assisted_claim_ep<T: WiFiProvTransportTrait>(&self, prov_mgr: &mut WifiProvMgr<T>) {
+        if factory::is_claimed(&self.factory_partition) {
+            log::info!("node already claimed. skipping assisted claiming");
+            return;
+        }
 
-        let mut buff = vec![0; 2500];
-        let node_id = rmaker_namespace.get_bytes("node_id", &mut buff).unwrap();
-        let client_cert = rmaker_namespace
-            .get_bytes("client_cert", &mut buff)
-            .unwrap();
-        let client_key = rmaker_namespace.get_bytes("client_key", &mut buff).unwrap();
+        let claim = claim::AssistedClaim::new(&self.node_id, self.factory_partition.clone());
+        prov_mgr.add_endpoint(
+            "rmaker_claim",
+            Box::new(move |_ep, data| -> Vec<u8> { claim.handle_request(data) }),
+        )
+    }
+}
 
-        if node_id.is_none() || client_cert.is_none() || client_key.is_none() {
-            let claimdata_notfound_error = "Please set RMAKER_CLAIMDATA_LOC env variable pointing to your rainmaker claimdata folder";
+#[cfg(target_os = "linux")]
+fn linux_init_claimdata(fctry_partition: &NvsPartition) -> Result<(), RmakerError> {
+    let storage_err = |_| RmakerError::ClaimData("could not access factory partition".to_string());
+    let mut rmaker_namespace =
+        Nvs::new(fctry_partition.clone(), "rmaker_creds").map_err(storage_err)?;
+
+    let mut buff = vec![0; 2500];
+    let mut is_stored = |key| {
+        rmaker_namespace
+            .get_bytes(key, &mut buff)
+            .map(|value| value.is_some())
+            .map_err(storage_err)
+    };
+    if is_stored("node_id")? && is_stored("client_cert")? && is_stored("client_key")? {
+        return Ok(());
+    }
 
-            let claimdata_loc = env::var("RMAKER_CLAIMDATA_PATH").expect(claimdata_notfound_error);
-            let claimdata_path = Path::new(claimdata_loc.as_str());
+    let claimdata_loc = env::var("RMAKER_CLAIMDATA_PATH").map_err(|_| {
+        RmakerError::ClaimData(
+            "Please set RMAKER_CLAIMDATA_PATH env variable pointing to your rainmaker claimdata folder"
+                .to_string(),
+        )
+    })?;
+    let claimdata_path = Path::new(claimdata_loc.as_str());
+    if !claimdata_path.exists() {
+        return Err(RmakerError::ClaimData(format!(
+            "claimdata folder {} doesn't exist",
+            claimdata_loc
+        )));
+    }
 
-            if !claimdata_path.exists() {
-                panic!("Claimdata folder doesn't exist");
-            }
+    for (key, file) in [
+        ("node_id", "node.info"),
+        ("client_cert", "node.crt"),
+        ("client_key", "node.key"),
+        ("random", "random.info"),
+    ] {
+        let value = fs::read_to_string(claimdata_path.join(file)).map_err(|e| {
+            RmakerError::ClaimData(format!(
+                "could not read {} from claimdata folder: {}",
+                file, e
+            ))
+        })?;
+        rmaker_namespace
+            .set_bytes(key, value.as_bytes())
+            .map_err(storage_err)?;
+    }
 
-            let node_id = claimdata_path.join("node.info");
-            let client_cert = claimdata_path.join("node.crt");
-            let client_key = claimdata_path.join("node.key");
-            let random = claimdata_path.join("random.info");
+    Ok(())
+}
 
-            if !node_id.exists() || !client_cert.exists() || !client_key.exists() {
-                panic!("Claimdata folder doesn't contain valid data");
-            }
+fn remote_params_callback(msg: ReceivedMessage, node: &Mutex<Node>) {
+    if let Err(e) = handle_params_update(&msg.payload, node) {
+        log::error!("invalid params payload: {}", e);
+    }
+}
+
+/// Dispatches a `{"device": {"param": value}}` update to the respective device callbacks.
+///
+/// Shared by updates received from the cloud and over local control. Values are validated
+/// against the respective params before the callbacks are executed. Values accepted by the
+/// callbacks are stored in the node, reported on `params/local` topic and passed to the write
+/// listeners of the node.
+pub(crate) fn handle_params_update(payload: &[u8], node: &Mutex<Node>) -> Result<(), RmakerError> {
+    let received_val: HashMap<String, HashMap<String, Value>> =
+        serde_json::from_slice(payload).map_err(|e| RmakerError::InvalidPayload(e.to_string()))?;
+    for (device, params) in received_val {
+        // lock is released before executing the callback so that it can access the node
+        let (params, callback) = {
+            let node = node.lock().unwrap();
+            let params = node.validate_param_values(&device, params);
+            (params, node.device_callback(&device))
+        };
+        if params.is_empty() {
+            continue;
+        }
 
-            rmaker_namespace
-                .set_bytes("node_id", fs::read_to_string(node_id).unwrap().as_bytes())
-                .unwrap();
-            rmaker_namespace
-                .set_bytes(
-                    "client_cert",
-                    fs::read_to_string(client_cert).unwrap().as_bytes(),
-                )
-                .unwrap();
-            rmaker_namespace
-                .set_bytes(
-                    "client_key",
-                    fs::read_to_string(client_key).unwrap().as_bytes(),
-                )
-                .unwrap();
-            rmaker_namespace
-                .set_bytes("random", fs::read_to_string(random).unwrap().as_bytes())
-                .unwrap();
+        let result = match callback {
+            Some(callback) => callback(params),
+            // nothing to apply the values to, node only acts as a store
+            None => ParamWriteResult::accept_all(params),
+        };
+        for param_name in result.rejected() {
+            log::warn!("{}.{}: write rejected by callback", device, param_name);
         }
+
+        let accepted = result.into_accepted();
+        if accepted.is_empty() {
+            continue;
+        }
+        let mut node = node.lock().unwrap();
+        if let Err(e) = node.report_param_values(&device, &accepted) {
+            log::error!("could not report accepted values of {}: {}", device, e);
+        }
+        node.notify_param_write(&device, &accepted);
     }
+
+    Ok(())
 }
 
-fn remote_params_callback(msg: ReceivedMessage, node: &Arc<Node>) {
-    let received_val: HashMap<String, HashMap<String, Value>> =
-        serde_json::from_str(&String::from_utf8(msg.payload).unwrap()).unwrap();
-    let devices = received_val.keys();
-    for device in devices {
-        let params = received_val.get(device).unwrap().to_owned();
-        node.exeute_device_callback(device, params);
+fn cloud_user_assoc_callback(
+    _ep: &str,
+    data: &[u8],
+    node_id: &str,
+    mqtt: &RmakerMqtt,
+    factory_partition: &NvsPartition,
+) -> Vec<u8> {
+    let status = match handle_user_mapping(data, node_id, mqtt, factory_partition) {
+        Ok(()) => RMakerConfigStatus::Success,
+        Err(e @ RmakerError::InvalidPayload(_)) => {
+            log::error!("invalid user mapping request: {}", e);
+            RMakerConfigStatus::InvalidParam
+        }
+        Err(e) => {
+            log::error!("could not publish user mapping payload: {}", e);
+            RMakerConfigStatus::InvalidState
+        }
+    };
+
+    let res_proto = RMakerConfigPayload {
+        msg: RMakerConfigMsgType::TypeRespSetUserMapping,
+        payload: mod_RMakerConfigPayload::OneOfpayload::resp_set_user_mapping(RespSetUserMapping {
+            Status: status,
+            NodeId: node_id.to_string(),
+        }),
+    };
+
+    let mut out_vec = vec![];
+    let mut writer = Writer::new(&mut out_vec);
+    if let Err(e) = res_proto.write_message(&mut writer) {
+        log::error!("could not encode user mapping response: {}", e);
+        return vec![];
     }
+
+    out_vec
 }
 
-fn cloud_user_assoc_callback(_ep: &str, data: &[u8], node_id: &str) -> Vec<u8> {
-    let req_proto = RMakerConfigPayload::try_from(data).unwrap();
-    let req_payload = req_proto.payload;
+fn handle_user_mapping(
+    data: &[u8],
+    node_id: &str,
+    mqtt: &RmakerMqtt,
+    factory_partition: &NvsPartition,
+) -> Result<(), RmakerError> {
+    let req_proto = RMakerConfigPayload::try_from(data)
+        .map_err(|e| RmakerError::InvalidPayload(e.to_string()))?;
 
-    let (user_id, secret_key) = match req_payload {
+    let (user_id, secret_key) = match req_proto.payload {
         mod_RMakerConfigPayload::OneOfpayload::cmd_set_user_mapping(p) => (p.UserID, p.SecretKey),
-        _ => unreachable!(),
+        _ => {
+            return Err(RmakerError::InvalidPayload(
+                "expected set user mapping command".to_string(),
+            ))
+        }
     };
 
     log::info!("received user_id={}, secret_key={}", user_id, secret_key);
@@ -248,57 +880,13 @@ fn cloud_user_assoc_callback(_ep: &str, data: &[u8], node_id: &str) -> Vec<u8> {
 
     let user_mapping_topic = format!("node/{}/{}", node_id, USER_MAPPING_TOPIC_SUFFIX);
 
-    if !rmaker_mqtt::is_mqtt_initialized() && rmaker_mqtt::init_rmaker_mqtt().is_err() {
-        // cannot publish user mapping payload
-        return vec![0];
+    if !mqtt.is_initialized() {
+        mqtt.init(factory_partition)?;
     }
-
-    if rmaker_mqtt::publish(
+    mqtt.publish(
         &user_mapping_topic,
         user_mapping_json.to_string().as_bytes().to_vec(),
-    )
-    .is_err()
-    {
-        log::error!("could not publish user mapping payload");
-    }
-
-    let res_proto = RMakerConfigPayload {
-        msg: RMakerConfigMsgType::TypeRespSetUserMapping,
-        payload: mod_RMakerConfigPayload::OneOfpayload::resp_set_user_mapping(RespSetUserMapping {
-            Status: RMakerConfigStatus::Success,
-            NodeId: node_id.to_string(),
-        }),
-    };
-
-    let mut out_vec = vec![];
-    let mut writer = Writer::new(&mut out_vec);
-
-    res_proto.write_message(&mut writer).unwrap();
-
-    out_vec
-}
-
-/// Reports parameters values of devices to the RainMaker cloud over MQTT.
-///
-/// Appropriate Device Name and a map of parameters(name: value) must be provided.
-///
-/// Example (Can be used in a device callback function)
-/// ```
-/// fn device_cb(params: HashMaps<String, Value>)
-/// {
-///     log::info!("Received update: {:?}", params);
-///     log::info!("Reporting: {:?}", params);
-///     rainmaker::report_params("DeviceName", params);
-/// }
-/// ```
-pub fn report_params(device_name: &str, params: HashMap<String, Value>) {
-    let updated_params = json!({
-        device_name: params
-    });
+    )?;
 
-    // TODO: cache this value somewhere?
-    let mut buff = [0u8; 32];
-    let node_id = factory::get_node_id(&mut buff).unwrap();
-    let local_params_topic = format!("node/{}/{}", node_id, NODE_PARAMS_LOCAL_TOPIC_SUFFIX);
-    rmaker_mqtt::publish(&local_params_topic, updated_params.to_string().into_bytes()).unwrap();
+    Ok(())
 }
@@ -0,0 +1,322 @@
+//! Local control service.
+//!
+//! Allows controlling the node from the phone application when both are on the same LAN, without
+//! going through the RainMaker cloud. The node is advertised over mDNS as `_esp_local_ctrl._tcp`
+//! and `esp_local_ctrl/*` protocomm endpoints are exposed over HTTP.
+//!
+//! Two properties are exposed to the clients, just like the C SDK:
+//! - `config`: node configuration (read only)
+//! - `params`: parameter values of all the devices and services. Writes to this property are
+//!   handled the same way as the writes received from the cloud on `params/remote` topic.
+//!
+//! This module is available with the `local-ctrl` feature.
+//!
+//! Example:
+//! ```rust
+//! let mut rmaker = RainmakerBuilder::new(factory_partition)?
+//!     .node(node)
+//!     .local_ctrl(LocalCtrlConfig::default())
+//!     .build()?;
+//! rmaker.start()?;
+//! ```
+
+use std::{collections::HashSet, sync::Mutex};
+
+use quick_protobuf::{MessageWrite, Writer};
+use rainmaker_components::protocomm::{
+    Protocomm, ProtocommHttpd, ProtocommHttpdConfig, ProtocommSecurity,
+};
+use serde_json::json;
+
+use crate::{
+    error::RmakerLocalCtrlError,
+    node::Node,
+    param::{Param, ParamProperty, ParamTypes, ParamUi, ParamValue},
+    proto::esp_local_ctrl::*,
+    service::{Service, ServiceType},
+    WrappedInArcMutex,
+};
+
+const LOCAL_CTRL_SERVICE_NAME: &str = "Local Control";
+const LOCAL_CTRL_PARAM_POP: &str = "POP";
+const LOCAL_CTRL_PARAM_TYPE: &str = "Type";
+
+const LOCAL_CTRL_MDNS_SERVICE_TYPE: &str = "_esp_local_ctrl";
+const LOCAL_CTRL_MDNS_PROTO: &str = "_tcp";
+const LOCAL_CTRL_VERSION_EP: &str = "esp_local_ctrl/version";
+const LOCAL_CTRL_SESSION_EP: &str = "esp_local_ctrl/session";
+const LOCAL_CTRL_CONTROL_EP: &str = "esp_local_ctrl/control";
+const LOCAL_CTRL_VERSION: &str = "v1.0";
+
+const PROP_TYPE_CONFIG: u32 = 1;
+const PROP_TYPE_PARAMS: u32 = 2;
+const PROP_FLAG_READONLY: u32 = 1;
+const PROP_NAME_CONFIG: &str = "config";
+const PROP_NAME_PARAMS: &str = "params";
+
+/// Security used for the local control sessions.
+#[derive(Debug, Clone)]
+pub enum LocalCtrlSecurity {
+    Sec0,
+    /// Proof of possession is optional and is reported to the phone application through the
+    /// node configuration.
+    Sec1(Option<String>),
+}
+
+/// Configuration for the local control service.
+#[derive(Debug, Clone)]
+pub struct LocalCtrlConfig {
+    pub port: u16,
+    pub security: LocalCtrlSecurity,
+}
+
+impl Default for LocalCtrlConfig {
+    fn default() -> Self {
+        Self {
+            port: 8080,
+            security: LocalCtrlSecurity::Sec1(None),
+        }
+    }
+}
+
+/// Running local control server of an agent.
+///
+/// Dropping it stops the server and the mDNS advertisement.
+pub(crate) struct LocalCtrl {
+    // kept around so that the server and mDNS advertisement live as long as the agent
+    _protocomm: Protocomm<ProtocommHttpd>,
+    #[cfg(target_os = "linux")]
+    mdns: mdns_sd::ServiceDaemon,
+    #[cfg(target_os = "espidf")]
+    _mdns: esp_idf_svc::mdns::EspMdns,
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for LocalCtrl {
+    fn drop(&mut self) {
+        // daemon thread keeps running till it is explicitly shut down
+        if self.mdns.shutdown().is_err() {
+            log::warn!("could not stop mDNS daemon");
+        }
+    }
+}
+
+pub(crate) fn create_local_ctrl_service(config: &LocalCtrlConfig) -> Service {
+    let (sec_type, pop) = match &config.security {
+        LocalCtrlSecurity::Sec0 => (0, String::new()),
+        LocalCtrlSecurity::Sec1(pop) => (1, pop.clone().unwrap_or_default()),
+    };
+
+    let mut local_ctrl_service = Service::new(LOCAL_CTRL_SERVICE_NAME, ServiceType::LocalControl);
+    local_ctrl_service.add_param(Param::new(
+        LOCAL_CTRL_PARAM_POP,
+        ParamValue::String(pop),
+        ParamTypes::LocalControlPOP,
+        HashSet::from([ParamProperty::Read]),
+        ParamUi::Hidden,
+    ));
+    local_ctrl_service.add_param(Param::new(
+        LOCAL_CTRL_PARAM_TYPE,
+        ParamValue::Integer(sec_type),
+        ParamTypes::LocalControlType,
+        HashSet::from([ParamProperty::Read]),
+        ParamUi::Hidden,
+    ));
+
+    local_ctrl_service
+}
+
+/// Starts the protocomm HTTP server and advertises the node over mDNS.
+///
+/// The server is stopped when the returned handle is dropped.
+pub(crate) fn start(
+    config: &LocalCtrlConfig,
+    node_id: &str,
+    node: WrappedInArcMutex<Node>,
+) -> Result<LocalCtrl, RmakerLocalCtrlError> {
+    let security = match &config.security {
+        LocalCtrlSecurity::Sec0 => ProtocommSecurity::new_sec0(),
+        LocalCtrlSecurity::Sec1(pop) => ProtocommSecurity::new_sec1(pop.clone()),
+    };
+
+    let mut protocomm = Protocomm::new(ProtocommHttpdConfig { port: config.port }, security);
+    let version_info = json!({
+        "local_ctrl": {
+            "ver": LOCAL_CTRL_VERSION,
+        }
+    });
+    protocomm
+        .set_version_info(LOCAL_CTRL_VERSION_EP, &version_info.to_string())
+        .map_err(|_| RmakerLocalCtrlError::ProtocommError)?;
+    protocomm
+        .set_security_endpoint(LOCAL_CTRL_SESSION_EP)
+        .map_err(|_| RmakerLocalCtrlError::ProtocommError)?;
+    protocomm
+        .register_endpoint(
+            LOCAL_CTRL_CONTROL_EP,
+            Box::new(move |_ep, data| local_ctrl_control_callback(data, &node)),
+        )
+        .map_err(|_| RmakerLocalCtrlError::ProtocommError)?;
+
+    let mdns = advertise(node_id, config.port)?;
+    log::info!("local control started on port {}", config.port);
+
+    Ok(LocalCtrl {
+        _protocomm: protocomm,
+        #[cfg(target_os = "linux")]
+        mdns,
+        #[cfg(target_os = "espidf")]
+        _mdns: mdns,
+    })
+}
+
+fn mdns_txt_records(node_id: &str) -> [(&str, &str); 4] {
+    [
+        ("version_endpoint", "/esp_local_ctrl/version"),
+        ("session_endpoint", "/esp_local_ctrl/session"),
+        ("control_endpoint", "/esp_local_ctrl/control"),
+        ("node_id", node_id),
+    ]
+}
+
+#[cfg(target_os = "linux")]
+fn advertise(node_id: &str, port: u16) -> Result<mdns_sd::ServiceDaemon, RmakerLocalCtrlError> {
+    use mdns_sd::{ServiceDaemon, ServiceInfo};
+
+    let mdns = ServiceDaemon::new().map_err(|_| RmakerLocalCtrlError::MdnsError)?;
+    let service_type = format!(
+        "{}.{}.local.",
+        LOCAL_CTRL_MDNS_SERVICE_TYPE, LOCAL_CTRL_MDNS_PROTO
+    );
+    let host_name = format!("{}.local.", node_id);
+    let service_info = ServiceInfo::new(
+        &service_type,
+        node_id,
+        &host_name,
+        "",
+        port,
+        &mdns_txt_records(node_id)[..],
+    )
+    .map_err(|_| RmakerLocalCtrlError::MdnsError)?
+    .enable_addr_auto();
+
+    mdns.register(service_info)
+        .map_err(|_| RmakerLocalCtrlError::MdnsError)?;
+
+    Ok(mdns)
+}
+
+#[cfg(target_os = "espidf")]
+fn advertise(node_id: &str, port: u16) -> Result<esp_idf_svc::mdns::EspMdns, RmakerLocalCtrlError> {
+    use esp_idf_svc::mdns::EspMdns;
+
+    let mut mdns = EspMdns::take().map_err(|_| RmakerLocalCtrlError::MdnsError)?;
+    mdns.set_hostname(node_id)
+        .map_err(|_| RmakerLocalCtrlError::MdnsError)?;
+    mdns.add_service(
+        Some(node_id),
+        LOCAL_CTRL_MDNS_SERVICE_TYPE,
+        LOCAL_CTRL_MDNS_PROTO,
+        port,
+        &mdns_txt_records(node_id),
+    )
+    .map_err(|_| RmakerLocalCtrlError::MdnsError)?;
+
+    Ok(mdns)
+}
+
+fn local_ctrl_control_callback(data: &[u8], node: &Mutex<Node>) -> Vec<u8> {
+    let req = match LocalCtrlMessage::try_from(data) {
+        Ok(req) => req,
+        Err(e) => {
+            log::error!("invalid local control request: {}", e);
+            return vec![];
+        }
+    };
+
+    let (msg, payload) = match req.payload {
+        mod_LocalCtrlMessage::OneOfpayload::cmd_get_prop_count(_) => (
+            LocalCtrlMsgType::TypeRespGetPropertyCount,
+            mod_LocalCtrlMessage::OneOfpayload::resp_get_prop_count(RespGetPropertyCount {
+                status: Status::Success,
+                count: 2,
+            }),
+        ),
+        mod_LocalCtrlMessage::OneOfpayload::cmd_get_prop_vals(cmd) => (
+            LocalCtrlMsgType::TypeRespGetPropertyValues,
+            mod_LocalCtrlMessage::OneOfpayload::resp_get_prop_vals(get_property_values(
+                &cmd.indices,
+                node,
+            )),
+        ),
+        mod_LocalCtrlMessage::OneOfpayload::cmd_set_prop_vals(cmd) => (
+            LocalCtrlMsgType::TypeRespSetPropertyValues,
+            mod_LocalCtrlMessage::OneOfpayload::resp_set_prop_vals(RespSetPropertyValues {
+                status: set_property_values(&cmd.props, node),
+            }),
+        ),
+        _ => {
+            log::error!("unexpected local control message: {:?}", req.msg);
+            return vec![];
+        }
+    };
+
+    let res = LocalCtrlMessage { msg, payload };
+    let mut out_vec = vec![];
+    let mut writer = Writer::new(&mut out_vec);
+    if res.write_message(&mut writer).is_err() {
+        log::error!("could not encode local control response");
+        return vec![];
+    }
+
+    out_vec
+}
+
+fn get_property_values(indices: &[u32], node: &Mutex<Node>) -> RespGetPropertyValues {
+    let node = node.lock().unwrap();
+    let mut props = vec![];
+    for index in indices {
+        let prop = match *index {
+            0 => PropertyInfo {
+                status: Status::Success,
+                name: PROP_NAME_CONFIG.to_string(),
+                type_pb: PROP_TYPE_CONFIG,
+                flags: PROP_FLAG_READONLY,
+                value: serde_json::to_vec(&*node).unwrap_or_default(),
+            },
+            1 => PropertyInfo {
+                status: Status::Success,
+                name: PROP_NAME_PARAMS.to_string(),
+                type_pb: PROP_TYPE_PARAMS,
+                flags: 0,
+                value: serde_json::to_vec(&node.get_param_values()).unwrap_or_default(),
+            },
+            _ => {
+                return RespGetPropertyValues {
+                    status: Status::InvalidArgument,
+                    props: vec![],
+                }
+            }
+        };
+        props.push(prop);
+    }
+
+    RespGetPropertyValues {
+        status: Status::Success,
+        props,
+    }
+}
+
+fn set_property_values(props: &[PropertyValue], node: &Mutex<Node>) -> Status {
+    for prop in props {
+        // only params property is writable
+        if prop.index != 1 {
+            return Status::InvalidArgument;
+        }
+        if crate::handle_params_update(&prop.value, node).is_err() {
+            return Status::InvalidArgument;
+        }
+    }
+
+    Status::Success
+}
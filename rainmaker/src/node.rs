@@ -5,6 +5,19 @@
 //! by [Node] instance
 //!
 //! Methods related node are implemented for struct [Node].
+//!
+//! Devices and parameters can be added, removed and renamed while the agent is running, e.g. by a
+//! gateway exposing child devices discovered after boot. The node is shared with the agent, so it
+//! is modified through [`Rainmaker::get_node`]. The agent republishes the node configuration on
+//! every such change and reports the values of the added parameters, while callbacks move along
+//! with their devices.
+//! ```rust
+//! let node = rmaker.get_node();
+//! let mut node = node.lock().unwrap();
+//! node.add_device(discovered_device);
+//! node.rename_device("Zigbee 0x1a2b", "Kitchen Light")?;
+//! node.remove_device("Porch Sensor");
+//! ```
 
 /*
 Node Id (node_id, String)
@@ -35,14 +48,38 @@ Devices (devices, Array of objects)
             Step (step, Number)
 */
 
-use std::{collections::HashMap, fmt::Debug};
+use std::{collections::HashMap, fmt::Debug, time::Instant};
 
 use serde::Serialize;
 use serde_json::Value;
 
-use crate::device::Device;
 #[allow(unused)]
 use crate::Rainmaker;
+use crate::{
+    constants::{
+        NODE_CONFIG_TOPIC_SUFFIX, NODE_CONFIG_VERSION, NODE_PARAMS_LOCAL_TOPIC_SUFFIX,
+        NODE_PARAMS_NOTIFY_TOPIC_SUFFIX, SIMPLE_TS_DATA_TOPIC_SUFFIX, TS_DATA_TOPIC_SUFFIX,
+    },
+    device::{Device, SharedDeviceCb},
+    error::RmakerError,
+    param::{Param, ParamProperty, ParamValue},
+    rmaker_mqtt::RmakerMqtt,
+    service::Service,
+    ts_data::{self, TsData, TsDataConfig},
+};
+
+// returns false once the listener is no longer interested in writes
+pub(crate) type ParamWriteListener =
+    Box<dyn FnMut(&str, &HashMap<String, ParamValue>) -> bool + Send + Sync + 'static>;
+
+#[derive(Default)]
+struct ParamWriteListeners(Vec<ParamWriteListener>);
+
+impl Debug for ParamWriteListeners {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} listener(s)", self.0.len())
+    }
+}
 
 #[derive(Debug, Clone, Serialize)]
 pub struct Info {
@@ -53,31 +90,50 @@ pub struct Info {
 #[derive(Debug, Serialize)]
 pub struct Node {
     node_id: String,
+    config_version: &'static str,
     #[serde(skip_serializing_if = "Option::is_none")]
     info: Option<Info>,
     #[serde(skip_serializing_if = "HashMap::is_empty")]
     attributes: HashMap<String, String>,
     devices: Vec<Device>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    services: Vec<Service>,
+    // set once the node is registered with an agent
+    #[serde(skip)]
+    mqtt: Option<RmakerMqtt>,
+    #[serde(skip)]
+    write_listeners: ParamWriteListeners,
+    #[serde(skip)]
+    ts_data: TsData,
 }
 
 impl Node {
-    /// An instance of node can be created using `new` method of the module. Node ID should be passed as an argument for the same. Node ID can be obtained from the method [`get_node_id`].
+    /// An instance of node can be created using `new` method of the module. Node ID should be passed as an argument for the same. Node ID can be obtained from the method [`node_id`].
     /// ```rust
-    /// let rmaker = Rainmaker::init()?;
-    /// let node_id = rmaker.get_node_id();
-    /// let mut node = Node::new(node_id);
+    /// let builder = RainmakerBuilder::new(factory_partition)?;
+    /// let mut node = Node::new(builder.node_id().to_string());
     /// ```
     ///
-    /// [`get_node_id`]: crate::Rainmaker::get_node_id  
+    /// [`node_id`]: crate::RainmakerBuilder::node_id
     pub fn new(node_id: String) -> Self {
         Self {
             node_id,
+            config_version: NODE_CONFIG_VERSION,
             info: None,
             attributes: HashMap::new(),
             devices: Vec::new(),
+            services: Vec::new(),
+            mqtt: None,
+            write_listeners: Default::default(),
+            ts_data: Default::default(),
         }
     }
 
+    /// Returns Node ID.
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
     /// Node information [Info] (Name, FW Version) is set using this function.
     /// ```rust
     /// node.set_info(Info{
@@ -89,11 +145,19 @@ impl Node {
         self.info = Some(info);
     }
 
+    /// Returns node information if set using [`set_info`](Node::set_info).
+    pub fn info(&self) -> Option<&Info> {
+        self.info.as_ref()
+    }
+
     /// Used to define attributes of node.
     pub fn set_attribute(&mut self, name: String, value: String) {
-        self.attributes
-            .insert(name, value)
-            .expect("Failed to set atttribute");
+        self.attributes.insert(name, value);
+    }
+
+    /// Returns device with the given name.
+    pub fn device(&self, device_name: &str) -> Option<&Device> {
+        self.devices.iter().find(|d| d.name() == device_name)
     }
 
     /// Multiple devices can be associated with the node by using this method. Instance of device should be passed as an argument.
@@ -103,9 +167,149 @@ impl Node {
     /// node.add_device(device);
     /// ```
     ///
+    /// Devices can also be added after the agent is started, e.g. by a gateway on discovering a
+    /// child device. The node configuration is then republished and the values of the parameters
+    /// of the device are reported. A device with the same name is replaced.
+    ///
     /// [device]: crate::device
-    pub fn add_device(&mut self, device: Device) {
-        self.devices.push(device);
+    pub fn add_device(&mut self, mut device: Device) {
+        let device_name = device.name().to_owned();
+        // persisted values of the devices added before the node is registered are restored by the agent
+        if self.mqtt.is_some() {
+            for param in device.params_mut() {
+                param.restore_value(&device_name);
+            }
+        }
+        let values = param_values(device.params());
+
+        match self.devices.iter_mut().find(|d| d.name() == device_name) {
+            Some(existing) => {
+                log::info!("replacing device {}", device_name);
+                *existing = device;
+            }
+            None => self.devices.push(device),
+        }
+        self.on_config_change(&device_name, &values);
+    }
+
+    /// Renames a device of the node.
+    ///
+    /// If the agent is started, the node configuration is republished and the values of the
+    /// parameters are reported under the new name. Values of persistent parameters are moved to
+    /// the new name as well. Returns [`RmakerError::DeviceNotFound`] if there is no such device
+    /// and [`RmakerError::DeviceExists`] if another device already has the new name.
+    pub fn rename_device(&mut self, device_name: &str, new_name: &str) -> Result<(), RmakerError> {
+        if self.params(new_name).is_some() {
+            return Err(RmakerError::DeviceExists(new_name.to_owned()));
+        }
+        let device = self
+            .devices
+            .iter_mut()
+            .find(|d| d.name() == device_name)
+            .ok_or_else(|| RmakerError::DeviceNotFound(device_name.to_owned()))?;
+
+        device.set_name(new_name);
+        for param in device.params() {
+            param.erase_value(device_name);
+            param.store_value(new_name);
+        }
+        let values = param_values(device.params());
+        self.on_config_change(new_name, &values);
+        Ok(())
+    }
+
+    /// Removes a device from the node and returns it.
+    ///
+    /// If the agent is started, the node configuration is republished without the device. Stored
+    /// values of its persistent parameters are erased.
+    pub fn remove_device(&mut self, device_name: &str) -> Option<Device> {
+        let index = self.devices.iter().position(|d| d.name() == device_name)?;
+        let device = self.devices.remove(index);
+        for param in device.params() {
+            param.erase_value(device_name);
+        }
+        self.on_config_change(device_name, &HashMap::new());
+        Some(device)
+    }
+
+    /// Adds a parameter to a device(or service) of the node.
+    ///
+    /// If the agent is started, the node configuration is republished and the value of the
+    /// parameter is reported. Returns [`RmakerError::DeviceNotFound`] if there is no such device.
+    pub fn add_param(&mut self, device_name: &str, mut param: Param) -> Result<(), RmakerError> {
+        if self.mqtt.is_some() {
+            param.restore_value(device_name);
+        }
+        let values = param_values(std::slice::from_ref(&param));
+        if let Some(device) = self.devices.iter_mut().find(|d| d.name() == device_name) {
+            device.add_param(param);
+        } else if let Some(service) = self.services.iter_mut().find(|s| s.name() == device_name) {
+            service.add_param(param);
+        } else {
+            return Err(RmakerError::DeviceNotFound(device_name.to_owned()));
+        }
+
+        self.on_config_change(device_name, &values);
+        Ok(())
+    }
+
+    /// Removes a parameter from a device(or service) of the node and returns it.
+    ///
+    /// If the agent is started, the node configuration is republished without the parameter. The
+    /// stored value of a persistent parameter is erased, so that it is not restored if a parameter
+    /// with the same name is added later.
+    pub fn remove_param(&mut self, device_name: &str, param_name: &str) -> Option<Param> {
+        let param = if let Some(device) = self.devices.iter_mut().find(|d| d.name() == device_name)
+        {
+            device.remove_param(param_name)?
+        } else {
+            self.services
+                .iter_mut()
+                .find(|s| s.name() == device_name)?
+                .remove_param(param_name)?
+        };
+        param.erase_value(device_name);
+        self.on_config_change(device_name, &HashMap::new());
+        Some(param)
+    }
+
+    /// Adds a service to the node. Standard services are usually added by the agent itself when
+    /// they are enabled, e.g. using [`ota`].
+    ///
+    /// [`ota`]: crate::RainmakerBuilder::ota
+    pub fn add_service(&mut self, service: Service) {
+        let service_name = service.name().to_owned();
+        let values = param_values(service.params());
+        self.services.push(service);
+        self.on_config_change(&service_name, &values);
+    }
+
+    /// Publishes the node configuration on `config` topic.
+    pub(crate) fn publish_config(&self) -> Result<(), RmakerError> {
+        let mqtt = match &self.mqtt {
+            Some(mqtt) if mqtt.is_initialized() => mqtt,
+            _ => return Ok(()),
+        };
+
+        let node_config =
+            serde_json::to_string(self).map_err(|e| RmakerError::ReportFailed(e.to_string()))?;
+        log::info!("publishing nodeconfig: {}", node_config);
+        let node_config_topic = format!("node/{}/{}", self.node_id, NODE_CONFIG_TOPIC_SUFFIX);
+        mqtt.publish(&node_config_topic, node_config.into())
+            .map_err(|e| RmakerError::ReportFailed(e.to_string()))
+    }
+
+    // config is republished only once the agent is started, which publishes the initial config
+    fn on_config_change(&mut self, device_name: &str, added: &HashMap<String, ParamValue>) {
+        if let Err(e) = self.publish_config() {
+            log::error!("could not republish node config: {}", e);
+        }
+        if added.is_empty() {
+            return;
+        }
+        if let Err(e) = self.publish_params(device_name, added, false) {
+            log::error!("could not report values of {}: {}", device_name, e);
+        }
     }
 
     pub(crate) fn get_param_values(&self) -> HashMap<&str, HashMap<&str, Value>> {
@@ -117,16 +321,336 @@ impl Node {
             }
             params.insert(dev.name(), curr_params);
         }
+        for service in &self.services {
+            let mut curr_params = HashMap::<&str, Value>::new();
+            for p in service.params() {
+                curr_params.insert(p.name(), p.value().clone().into());
+            }
+            params.insert(service.name(), curr_params);
+        }
 
         params
     }
 
-    pub(crate) fn exeute_device_callback(&self, device_name: &str, params: HashMap<String, Value>) {
-        for device in self.devices.iter() {
-            if device.name() == device_name {
-                device.execute_callback(params);
-                break;
+    /// Restores values of persistent parameters from NVS.
+    pub(crate) fn restore_persisted_params(&mut self) {
+        for device in self.devices.iter_mut() {
+            let device_name = device.name().to_owned();
+            for param in device.params_mut() {
+                param.restore_value(&device_name);
+            }
+        }
+        for service in self.services.iter_mut() {
+            let service_name = service.name().to_owned();
+            for param in service.params_mut() {
+                param.restore_value(&service_name);
+            }
+        }
+    }
+
+    /// Updates value of a parameter of a device(or service) and reports it to the RainMaker cloud.
+    ///
+    /// This keeps the node's own state model in sync with the values reported to the cloud and
+    /// local control clients.
+    /// ```rust
+    /// let node = rmaker.get_node();
+    /// node.lock()
+    ///     .unwrap()
+    ///     .update_param("Switch", "Power", ParamValue::Bool(true))?;
+    /// ```
+    pub fn update_param(
+        &mut self,
+        device_name: &str,
+        param_name: &str,
+        value: ParamValue,
+    ) -> Result<(), RmakerError> {
+        let params = HashMap::from([(param_name.to_owned(), value.clone())]);
+        let notify = self.is_notify_change(device_name, &params);
+
+        let param = self
+            .param_mut(device_name, param_name)
+            .ok_or(RmakerError::ParamNotFound)?;
+        param
+            .set_value(value)
+            .map_err(|_| RmakerError::InvalidParamValue)?;
+        param.store_value(device_name);
+
+        self.publish_params(device_name, &params, notify)
+    }
+
+    pub(crate) fn set_mqtt(&mut self, mqtt: RmakerMqtt) {
+        self.mqtt = Some(mqtt);
+    }
+
+    pub(crate) fn set_ts_data_config(&mut self, config: TsDataConfig) {
+        self.ts_data = TsData::new(config);
+    }
+
+    /// Registers a listener which is notified about the values applied by [`handle_params_update`].
+    ///
+    /// [`handle_params_update`]: crate::handle_params_update
+    #[cfg(feature = "async")]
+    pub(crate) fn add_write_listener(&mut self, listener: ParamWriteListener) {
+        self.write_listeners.0.push(listener);
+    }
+
+    pub(crate) fn notify_param_write(
+        &mut self,
+        device_name: &str,
+        values: &HashMap<String, ParamValue>,
+    ) {
+        self.write_listeners
+            .0
+            .retain_mut(|listener| listener(device_name, values));
+    }
+
+    /// Updates stored values of parameters of a device(or service) and reports them on
+    /// `params/local` topic.
+    ///
+    /// Returns [`RmakerError::DeviceNotFound`] if there is no such device(or service).
+    pub(crate) fn report_param_values(
+        &mut self,
+        device_name: &str,
+        values: &HashMap<String, ParamValue>,
+    ) -> Result<(), RmakerError> {
+        if self.params(device_name).is_none() {
+            return Err(RmakerError::DeviceNotFound(device_name.to_owned()));
+        }
+        let notify = self.is_notify_change(device_name, values);
+        self.set_param_values(device_name, values);
+        self.publish_params(device_name, values, notify)
+    }
+
+    // returns true if the values change any of the params having notifications enabled
+    fn is_notify_change(&self, device_name: &str, values: &HashMap<String, ParamValue>) -> bool {
+        let params = match self.params(device_name) {
+            Some(params) => params,
+            None => return false,
+        };
+        values.iter().any(|(param_name, value)| {
+            params
+                .iter()
+                .any(|p| p.name() == param_name && p.is_notify_enabled() && p.value() != value)
+        })
+    }
+
+    // values are reported only once the agent is started. initial values are reported by the agent
+    fn publish_params(
+        &mut self,
+        device_name: &str,
+        values: &HashMap<String, ParamValue>,
+        notify: bool,
+    ) -> Result<(), RmakerError> {
+        let mqtt = match &self.mqtt {
+            Some(mqtt) if mqtt.is_initialized() => mqtt.clone(),
+            _ => return Ok(()),
+        };
+
+        let params: HashMap<&str, Value> = values
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.clone().into()))
+            .collect();
+        let updated_params = serde_json::json!({ device_name: params });
+
+        let topic_suffix = match notify {
+            true => NODE_PARAMS_NOTIFY_TOPIC_SUFFIX,
+            false => NODE_PARAMS_LOCAL_TOPIC_SUFFIX,
+        };
+        let local_params_topic = format!("node/{}/{}", self.node_id, topic_suffix);
+        mqtt.publish(&local_params_topic, updated_params.to_string().into_bytes())
+            .map_err(|e| RmakerError::ReportFailed(e.to_string()))?;
+
+        self.publish_ts_data(&mqtt, device_name, values)
+    }
+
+    fn publish_ts_data(
+        &mut self,
+        mqtt: &RmakerMqtt,
+        device_name: &str,
+        values: &HashMap<String, ParamValue>,
+    ) -> Result<(), RmakerError> {
+        let mut ts_values = vec![];
+        let mut simple_ts_values = vec![];
+        for (param_name, value) in values {
+            let param = match self
+                .params(device_name)
+                .and_then(|params| params.iter().find(|p| p.name() == param_name))
+            {
+                Some(param) => param,
+                None => continue,
+            };
+            let name = format!("{}.{}", device_name, param_name);
+            if param.has_property(&ParamProperty::TimeSeries) {
+                ts_values.push((name.clone(), value));
+            }
+            if param.has_property(&ParamProperty::SimpleTimeSeries) {
+                simple_ts_values.push((name, value));
+            }
+        }
+        if ts_values.is_empty() && simple_ts_values.is_empty() {
+            return Ok(());
+        }
+
+        let timestamp = match ts_data::timestamp() {
+            Some(timestamp) => timestamp,
+            None => {
+                log::warn!("time not synced. dropping time series values");
+                return Ok(());
             }
+        };
+
+        let simple_ts_topic = format!("node/{}/{}", self.node_id, SIMPLE_TS_DATA_TOPIC_SUFFIX);
+        for (name, value) in simple_ts_values {
+            let payload = ts_data::simple_ts_payload(&name, value, timestamp);
+            mqtt.publish(&simple_ts_topic, payload)
+                .map_err(|e| RmakerError::ReportFailed(e.to_string()))?;
         }
+
+        for (name, value) in ts_values {
+            self.ts_data.add(name, value, timestamp);
+        }
+        if self.ts_data.is_due() {
+            self.flush_ts_data()?;
+        }
+
+        Ok(())
     }
+
+    /// Reports the pending time series values if the oldest of them is held for `max_delay`.
+    /// Returns the time by which the remaining values must be reported, if any.
+    pub(crate) fn flush_expired_ts_data(&mut self) -> Option<Instant> {
+        if self.ts_data.is_expired() {
+            if let Err(e) = self.flush_ts_data() {
+                log::error!("could not report time series values: {}", e);
+            }
+        }
+        self.ts_data.deadline()
+    }
+
+    /// Reports the pending time series values, if any.
+    pub(crate) fn flush_ts_data(&mut self) -> Result<(), RmakerError> {
+        let mqtt = match &self.mqtt {
+            Some(mqtt) if mqtt.is_initialized() => mqtt,
+            _ => return Ok(()),
+        };
+        let payload = match self.ts_data.take_payload() {
+            Some(payload) => payload,
+            None => return Ok(()),
+        };
+
+        let ts_topic = format!("node/{}/{}", self.node_id, TS_DATA_TOPIC_SUFFIX);
+        mqtt.publish(&ts_topic, payload)
+            .map_err(|e| RmakerError::ReportFailed(e.to_string()))
+    }
+
+    /// Validates values written by the user to parameters of a device(or service).
+    ///
+    /// Invalid values (unknown or read-only parameters, wrong type, out of bounds) are logged and
+    /// dropped, so that the callbacks only receive values they can apply.
+    pub(crate) fn validate_param_values(
+        &self,
+        device_name: &str,
+        values: HashMap<String, Value>,
+    ) -> HashMap<String, ParamValue> {
+        let params = match self.params(device_name) {
+            Some(params) => params,
+            None => {
+                log::error!("received params for unknown device {}", device_name);
+                return HashMap::new();
+            }
+        };
+
+        let mut validated = HashMap::new();
+        for (param_name, value) in values {
+            let param = match params.iter().find(|p| p.name() == param_name) {
+                Some(param) => param,
+                None => {
+                    log::error!(
+                        "rejecting write to unknown param {}.{}",
+                        device_name,
+                        param_name
+                    );
+                    continue;
+                }
+            };
+            match param.validate_write(&value) {
+                Ok(value) => {
+                    validated.insert(param_name, value);
+                }
+                Err(e) => log::error!(
+                    "rejecting write to {}.{} with value {}: {}",
+                    device_name,
+                    param_name,
+                    value,
+                    e
+                ),
+            }
+        }
+
+        validated
+    }
+
+    /// Updates stored values of parameters of a device(or service). Values of persistent
+    /// parameters are also stored to NVS.
+    ///
+    /// Unknown parameters and values of different type are ignored.
+    pub(crate) fn set_param_values(
+        &mut self,
+        device_name: &str,
+        values: &HashMap<String, ParamValue>,
+    ) {
+        for (param_name, value) in values {
+            let param = match self.param_mut(device_name, param_name) {
+                Some(param) => param,
+                None => continue,
+            };
+            match param.set_value(value.clone()) {
+                Ok(()) => param.store_value(device_name),
+                Err(value) => log::warn!(
+                    "ignoring value of different type for {}.{}: {:?}",
+                    device_name,
+                    param_name,
+                    value
+                ),
+            }
+        }
+    }
+
+    /// Returns the callback of a device(or service).
+    pub(crate) fn device_callback(&self, device_name: &str) -> Option<SharedDeviceCb> {
+        if let Some(device) = self.devices.iter().find(|d| d.name() == device_name) {
+            return device.callback();
+        }
+        self.services
+            .iter()
+            .find(|s| s.name() == device_name)
+            .and_then(|s| s.callback())
+    }
+
+    fn params(&self, device_name: &str) -> Option<&[Param]> {
+        if let Some(device) = self.devices.iter().find(|d| d.name() == device_name) {
+            return Some(device.params());
+        }
+        self.services
+            .iter()
+            .find(|s| s.name() == device_name)
+            .map(|s| s.params())
+    }
+
+    fn param_mut(&mut self, device_name: &str, param_name: &str) -> Option<&mut Param> {
+        if let Some(device) = self.devices.iter_mut().find(|d| d.name() == device_name) {
+            return device.param_mut(param_name);
+        }
+        self.services
+            .iter_mut()
+            .find(|s| s.name() == device_name)
+            .and_then(|s| s.param_mut(param_name))
+    }
+}
+
+fn param_values(params: &[Param]) -> HashMap<String, ParamValue> {
+    params
+        .iter()
+        .map(|p| (p.name().to_owned(), p.value().clone()))
+        .collect()
 }
@@ -0,0 +1,789 @@
+//! OTA firmware update service.
+//!
+//! Firmware updates are triggered from the RainMaker dashboard/CLI. The node receives the URL of
+//! the new image, downloads it over HTTPS and writes it to the inactive firmware slot through an
+//! [OtaWriter]. Progress of the update is reported back to the cloud.
+//!
+//! Two modes are supported, just like the C SDK:
+//! - [`OtaType::UsingTopics`]: URL is received on the `node/<node_id>/otaurl` topic and status is
+//!   reported on `node/<node_id>/otastatus`. This is the recommended mode.
+//! - [`OtaType::UsingParams`]: An `esp.service.ota` service with `esp.param.ota_url`,
+//!   `esp.param.ota_status` and `esp.param.ota_info` parameters is added to the node.
+//!
+//! When a new image boots for the first time it is marked as valid only after the node connects
+//! to the RainMaker cloud. If that doesn't happen within [`OtaConfig::rollback_timeout`], the
+//! previous image is restored.
+//!
+//! Example:
+//! ```rust
+//! let mut rmaker = RainmakerBuilder::new(factory_partition)?
+//!     .node(node)
+//!     .ota(OtaConfig::default())
+//!     .build()?;
+//! rmaker.start()?;
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Weak,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    constants::*,
+    device::ParamWriteResult,
+    error::RmakerOtaError,
+    node::Node,
+    param::{Param, ParamProperty, ParamTypes, ParamUi, ParamValue},
+    rmaker_mqtt::RmakerMqtt,
+    service::{Service, ServiceType},
+    utils::get_nvs_namespace,
+};
+
+#[cfg(target_os = "espidf")]
+use esp::download_image;
+#[cfg(target_os = "espidf")]
+pub use esp::EspOtaWriter;
+#[cfg(target_os = "linux")]
+use linux::download_image;
+#[cfg(target_os = "linux")]
+pub use linux::FileOtaWriter;
+
+const OTA_SERVICE_NAME: &str = "OTA";
+const OTA_PARAM_URL: &str = "URL";
+const OTA_PARAM_STATUS: &str = "Status";
+const OTA_PARAM_INFO: &str = "Info";
+const OTA_NVS_NAMESPACE: &str = "rmaker_ota";
+const OTA_NVS_JOB_ID_KEY: &str = "job_id";
+const OTA_DOWNLOAD_CHUNK_SIZE: usize = 4096;
+
+/// Destination for the downloaded firmware image.
+///
+/// Implementations for ESP ([EspOtaWriter], writing to `ota_0`/`ota_1` partitions) and Linux
+/// ([FileOtaWriter], writing to files in a directory) are provided by the crate.
+pub trait OtaWriter: Send + 'static {
+    /// Prepares the inactive slot for receiving a new image.
+    fn begin(&mut self, image_size: Option<usize>) -> Result<(), RmakerOtaError>;
+    /// Writes next chunk of the image.
+    fn write(&mut self, data: &[u8]) -> Result<(), RmakerOtaError>;
+    /// Validates the written image and sets it as the image to be booted next.
+    fn finish(&mut self) -> Result<(), RmakerOtaError>;
+    /// Discards the partially written image.
+    fn abort(&mut self);
+    /// Returns true if the running image is booted for the first time after an update.
+    fn is_pending_verify(&mut self) -> bool;
+    /// Marks the running image as valid, cancelling the rollback.
+    fn mark_valid(&mut self) -> Result<(), RmakerOtaError>;
+    /// Marks the running image as invalid and restores the previous one.
+    fn rollback(&mut self) -> Result<(), RmakerOtaError>;
+    /// Boots into the newly written image. Default implementation does nothing.
+    fn reboot(&mut self) {}
+}
+
+/// Mode in which OTA URL is received and status is reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtaType {
+    UsingTopics,
+    UsingParams,
+}
+
+/// Status of an OTA job as understood by the RainMaker cloud.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtaStatus {
+    InProgress,
+    Success,
+    Failed,
+    Delayed,
+    Rejected,
+}
+
+impl OtaStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            OtaStatus::InProgress => "in-progress",
+            OtaStatus::Success => "success",
+            OtaStatus::Failed => "failed",
+            OtaStatus::Delayed => "delayed",
+            OtaStatus::Rejected => "rejected",
+        }
+    }
+}
+
+/// Configuration for the OTA service.
+pub struct OtaConfig {
+    pub ota_type: OtaType,
+    /// Time to wait for cloud connection before rolling back a newly booted image.
+    pub rollback_timeout: Duration,
+    pub writer: Box<dyn OtaWriter>,
+}
+
+impl Default for OtaConfig {
+    fn default() -> Self {
+        Self {
+            ota_type: OtaType::UsingTopics,
+            rollback_timeout: Duration::from_secs(90),
+            #[cfg(target_os = "espidf")]
+            writer: Box::new(EspOtaWriter::new()),
+            #[cfg(target_os = "linux")]
+            writer: Box::new(FileOtaWriter::default()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct OtaJob {
+    #[serde(default)]
+    ota_job_id: Option<String>,
+    url: String,
+    #[serde(default)]
+    fw_version: Option<String>,
+    #[serde(default)]
+    file_size: Option<usize>,
+}
+
+/// State of the OTA service of an agent.
+pub(crate) struct Ota {
+    ota_type: OtaType,
+    rollback_timeout: Duration,
+    node_id: String,
+    writer: Mutex<Box<dyn OtaWriter>>,
+    in_progress: AtomicBool,
+    // image is validated only once, even if the agent is restarted meanwhile
+    validation_started: AtomicBool,
+    mqtt: RmakerMqtt,
+    node: Weak<Mutex<Node>>,
+}
+
+impl Ota {
+    pub(crate) fn new(
+        node_id: &str,
+        config: OtaConfig,
+        mqtt: RmakerMqtt,
+        node: Weak<Mutex<Node>>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            ota_type: config.ota_type,
+            rollback_timeout: config.rollback_timeout,
+            node_id: node_id.to_owned(),
+            writer: Mutex::new(config.writer),
+            in_progress: AtomicBool::new(false),
+            validation_started: AtomicBool::new(false),
+            mqtt,
+            node,
+        })
+    }
+
+    /// Returns the OTA service to be added to the node, if required by the OTA type.
+    pub(crate) fn service(self: &Arc<Self>) -> Option<Service> {
+        match self.ota_type {
+            OtaType::UsingParams => Some(create_ota_service(Arc::downgrade(self))),
+            OtaType::UsingTopics => None,
+        }
+    }
+
+    /// Subscribes to OTA topics and starts validation of a newly booted image if required.
+    ///
+    /// Called by the agent once MQTT is initialized.
+    pub(crate) fn start(self: &Arc<Self>, fw_version: &str) -> Result<(), RmakerOtaError> {
+        if !self.validation_started.swap(true, Ordering::SeqCst)
+            && self.writer.lock().unwrap().is_pending_verify()
+        {
+            let ota = self.clone();
+            thread::spawn(move || ota.validate_image());
+        }
+
+        if self.ota_type == OtaType::UsingTopics {
+            let otaurl_topic = format!("node/{}/{}", self.node_id, OTA_URL_TOPIC_SUFFIX);
+            let ota = Arc::downgrade(self);
+            self.mqtt.subscribe(&otaurl_topic, move |msg| {
+                let ota = match ota.upgrade() {
+                    Some(ota) => ota,
+                    None => return,
+                };
+                match serde_json::from_slice::<OtaJob>(&msg.payload) {
+                    Ok(job) => ota.handle_ota_job(job),
+                    Err(e) => log::error!("invalid OTA url payload: {}", e),
+                }
+            })?;
+
+            // request any pending OTA job for this node
+            let otafetch_topic = format!("node/{}/{}", self.node_id, OTA_FETCH_TOPIC_SUFFIX);
+            let fetch_payload = json!({
+                "node_id": self.node_id,
+                "fw_version": fw_version
+            });
+            self.mqtt
+                .publish(&otafetch_topic, fetch_payload.to_string().into_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    fn handle_ota_job(self: &Arc<Self>, job: OtaJob) {
+        if self.in_progress.swap(true, Ordering::SeqCst) {
+            log::warn!("OTA already in progress. rejecting new request");
+            self.report_status(
+                &job.ota_job_id,
+                OtaStatus::Rejected,
+                "OTA already in progress",
+            );
+            return;
+        }
+
+        log::info!(
+            "starting OTA: url={}, fw_version={:?}",
+            job.url,
+            job.fw_version
+        );
+
+        // download in a separate thread so that the MQTT callback is not blocked
+        let ota = self.clone();
+        thread::spawn(move || {
+            let res = ota.perform_ota(&job);
+            match res {
+                Ok(()) => {
+                    if let Some(job_id) = &job.ota_job_id {
+                        store_job_id(job_id);
+                    }
+                    ota.report_status(
+                        &job.ota_job_id,
+                        OtaStatus::InProgress,
+                        "Rebooting into new firmware",
+                    );
+                    // give some time for the status to be published
+                    thread::sleep(Duration::from_secs(2));
+                    ota.writer.lock().unwrap().reboot();
+                }
+                Err(e) => {
+                    log::error!("OTA failed: {}", e);
+                    ota.report_status(&job.ota_job_id, OtaStatus::Failed, &e.to_string());
+                }
+            }
+            ota.in_progress.store(false, Ordering::SeqCst);
+        });
+    }
+
+    fn perform_ota(&self, job: &OtaJob) -> Result<(), RmakerOtaError> {
+        let mut writer = self.writer.lock().unwrap();
+        self.report_status(
+            &job.ota_job_id,
+            OtaStatus::InProgress,
+            "Downloading firmware image",
+        );
+
+        writer.begin(job.file_size)?;
+
+        let mut last_reported = 0;
+        let res = download_image(&job.url, writer.as_mut(), &mut |written, total| {
+            // report progress every 10%
+            if let Some(total) = total.filter(|t| *t > 0) {
+                let percent = written * 100 / total;
+                if percent >= last_reported + 10 {
+                    last_reported = percent - percent % 10;
+                    self.report_status(
+                        &job.ota_job_id,
+                        OtaStatus::InProgress,
+                        &format!("Downloaded {}%", last_reported),
+                    );
+                }
+            }
+        });
+
+        if let Err(e) = res {
+            writer.abort();
+            return Err(e);
+        }
+
+        writer.finish()?;
+        log::info!("OTA image written successfully");
+
+        Ok(())
+    }
+
+    fn validate_image(&self) {
+        let job_id = take_job_id();
+
+        let started = Instant::now();
+        while !self.mqtt.is_connected() {
+            if started.elapsed() > self.rollback_timeout {
+                log::error!("could not connect to cloud with new firmware. rolling back");
+                let mut writer = self.writer.lock().unwrap();
+                if let Err(e) = writer.rollback() {
+                    log::error!("rollback failed: {}", e);
+                }
+                writer.reboot();
+                return;
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+
+        match self.writer.lock().unwrap().mark_valid() {
+            Ok(()) => {
+                log::info!("new firmware image marked as valid");
+                self.report_status(
+                    &job_id,
+                    OtaStatus::Success,
+                    "OTA upgrade finished successfully",
+                );
+            }
+            Err(e) => log::error!("could not mark firmware image as valid: {}", e),
+        }
+    }
+
+    fn report_status(&self, job_id: &Option<String>, status: OtaStatus, additional_info: &str) {
+        match self.ota_type {
+            OtaType::UsingTopics => {
+                let job_id = match job_id {
+                    Some(job_id) => job_id,
+                    None => return,
+                };
+                let status_payload = json!({
+                    "ota_job_id": job_id,
+                    "status": status.as_str(),
+                    "additional_info": additional_info
+                });
+                let otastatus_topic = format!("node/{}/{}", self.node_id, OTA_STATUS_TOPIC_SUFFIX);
+                if self
+                    .mqtt
+                    .publish(&otastatus_topic, status_payload.to_string().into_bytes())
+                    .is_err()
+                {
+                    log::error!("could not publish OTA status");
+                }
+            }
+            OtaType::UsingParams => {
+                let node = match self.node.upgrade() {
+                    Some(node) => node,
+                    None => return,
+                };
+                let params = HashMap::from([
+                    (
+                        OTA_PARAM_STATUS.to_string(),
+                        ParamValue::String(status.as_str().to_string()),
+                    ),
+                    (
+                        OTA_PARAM_INFO.to_string(),
+                        ParamValue::String(additional_info.to_string()),
+                    ),
+                ]);
+                let res = node
+                    .lock()
+                    .unwrap()
+                    .report_param_values(OTA_SERVICE_NAME, &params);
+                if res.is_err() {
+                    log::error!("could not report OTA status");
+                }
+            }
+        }
+    }
+}
+
+fn create_ota_service(ota: Weak<Ota>) -> Service {
+    let mut ota_service = Service::new(OTA_SERVICE_NAME, ServiceType::Ota);
+
+    let url_param = Param::new(
+        OTA_PARAM_URL,
+        ParamValue::String(String::new()),
+        ParamTypes::OTAURL,
+        HashSet::from([ParamProperty::Write]),
+        ParamUi::Hidden,
+    );
+    let status_param = Param::new(
+        OTA_PARAM_STATUS,
+        ParamValue::String(String::new()),
+        ParamTypes::OTAStatus,
+        HashSet::from([ParamProperty::Read]),
+        ParamUi::Text,
+    );
+    let info_param = Param::new(
+        OTA_PARAM_INFO,
+        ParamValue::String(String::new()),
+        ParamTypes::OTAInfo,
+        HashSet::from([ParamProperty::Read]),
+        ParamUi::Text,
+    );
+
+    ota_service.add_param(url_param);
+    ota_service.add_param(status_param);
+    ota_service.add_param(info_param);
+    ota_service.register_callback(Box::new(move |params| ota_service_cb(&ota, params)));
+
+    ota_service
+}
+
+fn ota_service_cb(ota: &Weak<Ota>, params: HashMap<String, ParamValue>) -> ParamWriteResult {
+    if let (Some(ota), Some(ParamValue::String(url))) = (ota.upgrade(), params.get(OTA_PARAM_URL)) {
+        ota.handle_ota_job(OtaJob {
+            ota_job_id: None,
+            url: url.to_owned(),
+            fw_version: None,
+            file_size: None,
+        });
+    }
+
+    // URL is not reported back. progress is reported using status and info params
+    ParamWriteResult::new()
+}
+
+fn store_job_id(job_id: &str) {
+    if let Some(mut nvs) = get_nvs_namespace(OTA_NVS_NAMESPACE) {
+        if nvs
+            .set_bytes(OTA_NVS_JOB_ID_KEY, job_id.as_bytes())
+            .is_err()
+        {
+            log::error!("could not store OTA job id");
+        }
+    }
+}
+
+fn take_job_id() -> Option<String> {
+    let mut nvs = get_nvs_namespace(OTA_NVS_NAMESPACE)?;
+    let mut buff = [0u8; 64];
+    let job_id = match nvs.get_bytes(OTA_NVS_JOB_ID_KEY, &mut buff) {
+        Ok(Some(bytes)) if !bytes.is_empty() => String::from_utf8(bytes).ok(),
+        _ => None,
+    };
+    // job id is only required for reporting the first boot of new image
+    let _ = nvs.set_bytes(OTA_NVS_JOB_ID_KEY, &[]);
+
+    job_id
+}
+
+mod esp {
+    #![cfg(target_os = "espidf")]
+
+    use esp_idf_svc::{
+        http::{
+            client::{Configuration, EspHttpConnection},
+            Method,
+        },
+        sys::{self, esp},
+    };
+
+    use super::{OtaWriter, OTA_DOWNLOAD_CHUNK_SIZE};
+    use crate::error::RmakerOtaError;
+
+    /// Writes the firmware image to the next `ota_*` app partition using ESP-IDF OTA APIs.
+    pub struct EspOtaWriter {
+        handle: sys::esp_ota_handle_t,
+        partition: *const sys::esp_partition_t,
+    }
+
+    // partition pointers point to static partition table entries
+    unsafe impl Send for EspOtaWriter {}
+
+    impl EspOtaWriter {
+        pub fn new() -> Self {
+            Self {
+                handle: 0,
+                partition: std::ptr::null(),
+            }
+        }
+    }
+
+    impl Default for EspOtaWriter {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl OtaWriter for EspOtaWriter {
+        fn begin(&mut self, image_size: Option<usize>) -> Result<(), RmakerOtaError> {
+            unsafe {
+                let partition = sys::esp_ota_get_next_update_partition(std::ptr::null());
+                if partition.is_null() {
+                    return Err(RmakerOtaError::WriteError(
+                        "no OTA partition found".to_string(),
+                    ));
+                }
+                let image_size = image_size.unwrap_or(sys::OTA_SIZE_UNKNOWN as usize);
+                esp!(sys::esp_ota_begin(partition, image_size, &mut self.handle))
+                    .map_err(|e| RmakerOtaError::WriteError(e.to_string()))?;
+                self.partition = partition;
+            }
+            Ok(())
+        }
+
+        fn write(&mut self, data: &[u8]) -> Result<(), RmakerOtaError> {
+            esp!(unsafe { sys::esp_ota_write(self.handle, data.as_ptr() as _, data.len()) })
+                .map_err(|e| RmakerOtaError::WriteError(e.to_string()))
+        }
+
+        fn finish(&mut self) -> Result<(), RmakerOtaError> {
+            unsafe {
+                esp!(sys::esp_ota_end(self.handle))
+                    .map_err(|e| RmakerOtaError::ImageValidationFailed(e.to_string()))?;
+                esp!(sys::esp_ota_set_boot_partition(self.partition))
+                    .map_err(|e| RmakerOtaError::WriteError(e.to_string()))?;
+            }
+            self.handle = 0;
+            Ok(())
+        }
+
+        fn abort(&mut self) {
+            if self.handle != 0 {
+                unsafe { sys::esp_ota_abort(self.handle) };
+                self.handle = 0;
+            }
+        }
+
+        fn is_pending_verify(&mut self) -> bool {
+            let mut state: sys::esp_ota_img_states_t = 0;
+            unsafe {
+                let running = sys::esp_ota_get_running_partition();
+                esp!(sys::esp_ota_get_state_partition(running, &mut state)).is_ok()
+                    && state == sys::esp_ota_img_states_t_ESP_OTA_IMG_PENDING_VERIFY
+            }
+        }
+
+        fn mark_valid(&mut self) -> Result<(), RmakerOtaError> {
+            esp!(unsafe { sys::esp_ota_mark_app_valid_cancel_rollback() })
+                .map_err(|e| RmakerOtaError::RollbackError(e.to_string()))
+        }
+
+        fn rollback(&mut self) -> Result<(), RmakerOtaError> {
+            // reboots the chip on success
+            esp!(unsafe { sys::esp_ota_mark_app_invalid_rollback_and_reboot() })
+                .map_err(|e| RmakerOtaError::RollbackError(e.to_string()))
+        }
+
+        fn reboot(&mut self) {
+            unsafe { sys::esp_restart() };
+        }
+    }
+
+    pub(super) fn download_image(
+        url: &str,
+        writer: &mut dyn OtaWriter,
+        on_progress: &mut dyn FnMut(usize, Option<usize>),
+    ) -> Result<(), RmakerOtaError> {
+        let download_err = |e: sys::EspError| RmakerOtaError::DownloadError(e.to_string());
+
+        let mut conn = EspHttpConnection::new(&Configuration {
+            crt_bundle_attach: Some(sys::esp_crt_bundle_attach),
+            ..Default::default()
+        })
+        .map_err(download_err)?;
+
+        conn.initiate_request(Method::Get, url, &[])
+            .map_err(download_err)?;
+        conn.initiate_response().map_err(download_err)?;
+
+        let status = conn.status();
+        if !(200..300).contains(&status) {
+            return Err(RmakerOtaError::DownloadError(format!(
+                "server responded with status {}",
+                status
+            )));
+        }
+
+        let total = conn
+            .header("Content-Length")
+            .and_then(|len| len.parse::<usize>().ok());
+
+        let mut buff = vec![0u8; OTA_DOWNLOAD_CHUNK_SIZE];
+        let mut written = 0;
+        loop {
+            let len = conn.read(&mut buff).map_err(download_err)?;
+            if len == 0 {
+                break;
+            }
+            writer.write(&buff[..len])?;
+            written += len;
+            on_progress(written, total);
+        }
+
+        if total.is_some_and(|total| total != written) {
+            return Err(RmakerOtaError::DownloadError(
+                "incomplete image received".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+mod linux {
+    #![cfg(target_os = "linux")]
+
+    use std::{
+        fs::{self, File},
+        io::{Read, Write},
+        path::PathBuf,
+    };
+
+    use serde::{Deserialize, Serialize};
+
+    use super::{OtaWriter, OTA_DOWNLOAD_CHUNK_SIZE};
+    use crate::error::RmakerOtaError;
+
+    const SLOTS: [&str; 2] = ["ota_0", "ota_1"];
+    const STATE_FILE: &str = "ota_state.json";
+
+    #[derive(Debug, Default, Serialize, Deserialize)]
+    struct SlotState {
+        active: usize,
+        pending_verify: bool,
+    }
+
+    /// Writes the firmware image to one of two file based slots(`ota_0`, `ota_1`) in a directory.
+    ///
+    /// The slot to be executed is recorded in `ota_state.json` in the same directory and can be
+    /// queried using [`active_image_path`](FileOtaWriter::active_image_path). Executing the
+    /// image is left to the process supervisor (e.g. a systemd unit or wrapper script).
+    pub struct FileOtaWriter {
+        dir: PathBuf,
+        file: Option<File>,
+    }
+
+    impl FileOtaWriter {
+        pub fn new(dir: impl Into<PathBuf>) -> Self {
+            Self {
+                dir: dir.into(),
+                file: None,
+            }
+        }
+
+        /// Returns path of the image which should be executed.
+        pub fn active_image_path(&self) -> PathBuf {
+            self.dir.join(SLOTS[self.read_state().active])
+        }
+
+        fn read_state(&self) -> SlotState {
+            fs::read(self.dir.join(STATE_FILE))
+                .ok()
+                .and_then(|state| serde_json::from_slice(&state).ok())
+                .unwrap_or_default()
+        }
+
+        fn write_state(&self, state: &SlotState) -> Result<(), RmakerOtaError> {
+            let state =
+                serde_json::to_vec(state).map_err(|e| RmakerOtaError::WriteError(e.to_string()))?;
+            fs::write(self.dir.join(STATE_FILE), state)
+                .map_err(|e| RmakerOtaError::WriteError(e.to_string()))
+        }
+
+        fn inactive_slot(&self) -> usize {
+            (self.read_state().active + 1) % SLOTS.len()
+        }
+    }
+
+    impl Default for FileOtaWriter {
+        /// Uses `~/.config/rmaker/ota` for storing the images.
+        fn default() -> Self {
+            let home = std::env::var("HOME").unwrap_or_default();
+            Self::new(PathBuf::from(home).join(".config/rmaker/ota"))
+        }
+    }
+
+    impl OtaWriter for FileOtaWriter {
+        fn begin(&mut self, _image_size: Option<usize>) -> Result<(), RmakerOtaError> {
+            fs::create_dir_all(&self.dir).map_err(|e| RmakerOtaError::WriteError(e.to_string()))?;
+            let slot = self.dir.join(SLOTS[self.inactive_slot()]);
+            let file = File::create(slot).map_err(|e| RmakerOtaError::WriteError(e.to_string()))?;
+            self.file = Some(file);
+            Ok(())
+        }
+
+        fn write(&mut self, data: &[u8]) -> Result<(), RmakerOtaError> {
+            match self.file.as_mut() {
+                Some(file) => file
+                    .write_all(data)
+                    .map_err(|e| RmakerOtaError::WriteError(e.to_string())),
+                None => Err(RmakerOtaError::NotInitialized),
+            }
+        }
+
+        fn finish(&mut self) -> Result<(), RmakerOtaError> {
+            let file = self.file.take().ok_or(RmakerOtaError::NotInitialized)?;
+            file.sync_all()
+                .map_err(|e| RmakerOtaError::WriteError(e.to_string()))?;
+            if file.metadata().map(|m| m.len()).unwrap_or(0) == 0 {
+                return Err(RmakerOtaError::ImageValidationFailed(
+                    "empty image".to_string(),
+                ));
+            }
+
+            self.write_state(&SlotState {
+                active: self.inactive_slot(),
+                pending_verify: true,
+            })
+        }
+
+        fn abort(&mut self) {
+            if self.file.take().is_some() {
+                let _ = fs::remove_file(self.dir.join(SLOTS[self.inactive_slot()]));
+            }
+        }
+
+        fn is_pending_verify(&mut self) -> bool {
+            self.read_state().pending_verify
+        }
+
+        fn mark_valid(&mut self) -> Result<(), RmakerOtaError> {
+            let state = self.read_state();
+            self.write_state(&SlotState {
+                active: state.active,
+                pending_verify: false,
+            })
+        }
+
+        fn rollback(&mut self) -> Result<(), RmakerOtaError> {
+            self.write_state(&SlotState {
+                active: self.inactive_slot(),
+                pending_verify: false,
+            })
+        }
+
+        fn reboot(&mut self) {
+            log::info!(
+                "new image is available at {}. restart the application to boot it",
+                self.active_image_path().display()
+            );
+        }
+    }
+
+    pub(super) fn download_image(
+        url: &str,
+        writer: &mut dyn OtaWriter,
+        on_progress: &mut dyn FnMut(usize, Option<usize>),
+    ) -> Result<(), RmakerOtaError> {
+        let response = ureq::get(url)
+            .call()
+            .map_err(|e| RmakerOtaError::DownloadError(e.to_string()))?;
+
+        let total = response
+            .header("Content-Length")
+            .and_then(|len| len.parse::<usize>().ok());
+
+        let mut reader = response.into_reader();
+        let mut buff = vec![0u8; OTA_DOWNLOAD_CHUNK_SIZE];
+        let mut written = 0;
+        loop {
+            let len = reader
+                .read(&mut buff)
+                .map_err(|e| RmakerOtaError::DownloadError(e.to_string()))?;
+            if len == 0 {
+                break;
+            }
+            writer.write(&buff[..len])?;
+            written += len;
+            on_progress(written, total);
+        }
+
+        if total.is_some_and(|total| total != written) {
+            return Err(RmakerOtaError::DownloadError(
+                "incomplete image received".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
@@ -5,9 +5,18 @@
 //! Initialization for standard parameters(Power, Brightness, Hue, Saturation) can be done using specified standard methods.
 
 use serde::Serialize;
-use serde_json::{Number, Value};
+use serde_json::{Map, Number, Value};
 use std::collections::HashSet;
 
+use crate::{
+    error::RmakerParamError,
+    utils::{get_nvs_namespace, hashed_nvs_key},
+};
+
+const PARAMS_NVS_NAMESPACE: &str = "rmaker_params";
+// maximum size of a stored value in JSON, restore buffer must fit it
+const PARAM_VALUE_MAX_STORED_SIZE: usize = 4000;
+
 #[derive(Debug, Serialize)]
 pub struct Param {
     name: String,
@@ -18,6 +27,10 @@ pub struct Param {
     bounds: Option<ParamBounds>,
     #[serde(rename = "data_type")]
     value: ParamValue,
+    #[serde(skip)]
+    persist: bool,
+    #[serde(skip)]
+    notify: bool,
 }
 
 /// Set of access mode parameter.
@@ -26,15 +39,26 @@ pub struct Param {
 pub enum ParamProperty {
     Read,
     Write,
+    /// Reported values are also stored by the cloud as time series data. See [ts_data].
+    ///
+    /// [ts_data]: crate::ts_data
+    #[serde(rename = "time_series")]
+    TimeSeries,
+    /// Same as [`TimeSeries`](ParamProperty::TimeSeries), but each value is reported as soon
+    /// as it changes without any batching.
+    #[serde(rename = "simple_ts")]
+    SimpleTimeSeries,
 }
 
 /// Set of the type of parameter value.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ParamValue {
     String(String),
     Bool(bool),
     Integer(i64),
     Float(f64),
+    Array(Vec<Value>),
+    Object(Map<String, Value>),
 }
 
 /// Set of the parameter type.
@@ -86,6 +110,10 @@ pub enum ParamTypes {
     FactoryReset,
     #[serde(rename = "esp.param.wifi-reset")]
     WiFiReset,
+    #[serde(rename = "esp.param.local_control_pop")]
+    LocalControlPOP,
+    #[serde(rename = "esp.param.local_control_type")]
+    LocalControlType,
     #[serde(rename = "esp.param.toggle")]
     ToggleController,
     #[serde(rename = "esp.param.range")]
@@ -178,6 +206,8 @@ impl Param {
             properties,
             ui_type,
             bounds: None,
+            persist: false,
+            notify: false,
         }
     }
 
@@ -191,6 +221,159 @@ impl Param {
         &self.value
     }
 
+    /// Updates current state of parameter.
+    ///
+    /// The new value must be of the same type as the initial value of the parameter, otherwise
+    /// it is returned back as an error.
+    ///
+    /// This only updates the local model. Use [`Node::update_param`] for updating as well as
+    /// reporting the value.
+    ///
+    /// [`Node::update_param`]: crate::node::Node::update_param
+    pub fn set_value(&mut self, value: ParamValue) -> Result<(), ParamValue> {
+        if std::mem::discriminant(&self.value) != std::mem::discriminant(&value) {
+            return Err(value);
+        }
+        self.value = value;
+
+        Ok(())
+    }
+
+    /// Validates a value written to the parameter by the user(over cloud or local control).
+    ///
+    /// Parameter must have [`ParamProperty::Write`] property and the value must be of the same
+    /// type as the parameter and within its bounds(if any).
+    pub(crate) fn validate_write(&self, value: &Value) -> Result<ParamValue, RmakerParamError> {
+        if !self.properties.contains(&ParamProperty::Write) {
+            return Err(RmakerParamError::NotWritable);
+        }
+
+        let value = self
+            .value
+            .convert_json(value)
+            .ok_or(RmakerParamError::InvalidType)?;
+
+        if let Some(bounds) = &self.bounds {
+            let in_bounds = match value {
+                ParamValue::Integer(v) => (bounds.min as i64..=bounds.max as i64).contains(&v),
+                ParamValue::Float(v) => (bounds.min as f64..=bounds.max as f64).contains(&v),
+                _ => true,
+            };
+            if !in_bounds {
+                return Err(RmakerParamError::OutOfBounds);
+            }
+        }
+
+        Ok(value)
+    }
+
+    /// Adds a property to the parameter, e.g. [`ParamProperty::TimeSeries`].
+    pub fn add_property(&mut self, property: ParamProperty) {
+        self.properties.insert(property);
+    }
+
+    /// Returns true if the parameter has the property.
+    pub fn has_property(&self, property: &ParamProperty) -> bool {
+        self.properties.contains(property)
+    }
+
+    /// Enables storing the reported values of the parameter in NVS.
+    ///
+    /// Stored value is restored when the node is registered with the agent, so the last state
+    /// set by the user survives a reboot. Values longer than 4000 bytes in JSON, e.g. large
+    /// arrays or objects, are not stored and the previously stored value is erased.
+    pub fn set_persist(&mut self, persist: bool) {
+        self.persist = persist;
+    }
+
+    /// Returns true if values of the parameter are stored in NVS.
+    pub fn is_persistent(&self) -> bool {
+        self.persist
+    }
+
+    /// Enables push notifications on the phone when the reported value of the parameter changes,
+    /// e.g. for alarm states.
+    ///
+    /// Changed values are reported on `params/local/notify` topic instead of `params/local`.
+    pub fn set_notify(&mut self, notify: bool) {
+        self.notify = notify;
+    }
+
+    /// Returns true if changes to the parameter value trigger push notifications.
+    pub fn is_notify_enabled(&self) -> bool {
+        self.notify
+    }
+
+    /// Restores the value stored in NVS, if any. Stored values of different type are ignored.
+    pub(crate) fn restore_value(&mut self, owner_name: &str) {
+        if !self.persist {
+            return;
+        }
+        let nvs = match get_nvs_namespace(PARAMS_NVS_NAMESPACE) {
+            Some(nvs) => nvs,
+            None => return,
+        };
+
+        let mut buff = vec![0u8; PARAM_VALUE_MAX_STORED_SIZE];
+        let key = param_nvs_key(owner_name, &self.name);
+        let stored_value = match nvs.get_bytes(&key, &mut buff) {
+            Ok(Some(bytes)) => serde_json::from_slice::<Value>(&bytes).ok(),
+            _ => None,
+        };
+
+        if let Some(value) = stored_value.and_then(|v| self.value.convert_json(&v)) {
+            log::info!("restored {}.{} = {:?}", owner_name, self.name, value);
+            self.value = value;
+        }
+    }
+
+    /// Stores the current value in NVS if the parameter is persistent.
+    pub(crate) fn store_value(&self, owner_name: &str) {
+        if !self.persist {
+            return;
+        }
+        let mut nvs = match get_nvs_namespace(PARAMS_NVS_NAMESPACE) {
+            Some(nvs) => nvs,
+            None => return,
+        };
+
+        let key = param_nvs_key(owner_name, &self.name);
+        let value = Value::from(self.value.clone()).to_string();
+        // oversized value could not be restored, so the previously stored value is erased instead
+        // of being restored later
+        let value = if value.len() > PARAM_VALUE_MAX_STORED_SIZE {
+            log::error!(
+                "value of {}.{} is too large to be stored: {} bytes",
+                owner_name,
+                self.name,
+                value.len()
+            );
+            String::new()
+        } else {
+            value
+        };
+        if nvs.set_bytes(&key, value.as_bytes()).is_err() {
+            log::error!("could not store value of {}.{}", owner_name, self.name);
+        }
+    }
+
+    /// Erases the value stored in NVS, e.g. once the owner is renamed or removed.
+    pub(crate) fn erase_value(&self, owner_name: &str) {
+        if !self.persist {
+            return;
+        }
+        let mut nvs = match get_nvs_namespace(PARAMS_NVS_NAMESPACE) {
+            Some(nvs) => nvs,
+            None => return,
+        };
+
+        // empty value is treated as not stored
+        let key = param_nvs_key(owner_name, &self.name);
+        if nvs.set_bytes(&key, &[]).is_err() {
+            log::error!("could not erase value of {}.{}", owner_name, self.name);
+        }
+    }
+
     /// Assigns minimum and maximum value to a parameter.
     pub fn add_bounds(&mut self, min: i32, max: i32, step: i32) {
         self.bounds = Some(ParamBounds { min, max, step })
@@ -267,17 +450,44 @@ impl Param {
     }
 }
 
+impl ParamValue {
+    /// Converts JSON value to a [ParamValue] of the same type as `self`.
+    pub(crate) fn convert_json(&self, value: &Value) -> Option<ParamValue> {
+        match (self, value) {
+            (ParamValue::String(_), Value::String(v)) => Some(ParamValue::String(v.to_owned())),
+            (ParamValue::Bool(_), Value::Bool(v)) => Some(ParamValue::Bool(*v)),
+            (ParamValue::Integer(_), Value::Number(v)) => v.as_i64().map(ParamValue::Integer),
+            // integers are valid values for float params
+            (ParamValue::Float(_), Value::Number(v)) => v.as_f64().map(ParamValue::Float),
+            (ParamValue::Array(_), Value::Array(v)) => Some(ParamValue::Array(v.to_owned())),
+            (ParamValue::Object(_), Value::Object(v)) => Some(ParamValue::Object(v.to_owned())),
+            _ => None,
+        }
+    }
+
+    /// Returns the RainMaker data type of the value.
+    pub(crate) fn data_type(&self) -> &'static str {
+        match self {
+            ParamValue::String(_) => "string",
+            ParamValue::Bool(_) => "bool",
+            ParamValue::Integer(_) => "int",
+            ParamValue::Float(_) => "float",
+            ParamValue::Array(_) => "array",
+            ParamValue::Object(_) => "object",
+        }
+    }
+}
+
+fn param_nvs_key(owner_name: &str, param_name: &str) -> String {
+    hashed_nvs_key("p", &format!("{}.{}", owner_name, param_name))
+}
+
 impl Serialize for ParamValue {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        serializer.serialize_str(match self {
-            ParamValue::String(_) => "string",
-            ParamValue::Bool(_) => "bool",
-            ParamValue::Integer(_) => "int",
-            ParamValue::Float(_) => "float",
-        })
+        serializer.serialize_str(self.data_type())
     }
 }
 
@@ -287,7 +497,10 @@ impl From<ParamValue> for Value {
             ParamValue::String(v) => Self::String(v),
             ParamValue::Bool(v) => Self::Bool(v),
             ParamValue::Integer(v) => Self::Number(Number::from(v)),
-            ParamValue::Float(v) => Self::Number(Number::from_f64(v).unwrap()),
+            // NaN and infinity cannot be represented in JSON
+            ParamValue::Float(v) => Number::from_f64(v).map_or(Self::Null, Self::Number),
+            ParamValue::Array(v) => Self::Array(v),
+            ParamValue::Object(v) => Self::Object(v),
         }
     }
 }
@@ -0,0 +1,273 @@
+// Automatically generated rust module for 'esp_rmaker_claim.proto' file
+
+#![allow(non_snake_case)]
+#![allow(non_upper_case_globals)]
+#![allow(non_camel_case_types)]
+#![allow(unused_imports)]
+#![allow(unknown_lints)]
+#![allow(clippy::all)]
+#![cfg_attr(rustfmt, rustfmt_skip)]
+
+
+use quick_protobuf::{MessageInfo, MessageRead, MessageWrite, BytesReader, Writer, WriterBackend, Result};
+use core::convert::{TryFrom, TryInto};
+use quick_protobuf::sizeofs::*;
+use super::*;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RMakerClaimStatus {
+    Success = 0,
+    Fail = 1,
+    InvalidParam = 2,
+    InvalidState = 3,
+    NoMemory = 4,
+}
+
+impl Default for RMakerClaimStatus {
+    fn default() -> Self {
+        RMakerClaimStatus::Success
+    }
+}
+
+impl From<i32> for RMakerClaimStatus {
+    fn from(i: i32) -> Self {
+        match i {
+            0 => RMakerClaimStatus::Success,
+            1 => RMakerClaimStatus::Fail,
+            2 => RMakerClaimStatus::InvalidParam,
+            3 => RMakerClaimStatus::InvalidState,
+            4 => RMakerClaimStatus::NoMemory,
+            _ => Self::default(),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for RMakerClaimStatus {
+    fn from(s: &'a str) -> Self {
+        match s {
+            "Success" => RMakerClaimStatus::Success,
+            "Fail" => RMakerClaimStatus::Fail,
+            "InvalidParam" => RMakerClaimStatus::InvalidParam,
+            "InvalidState" => RMakerClaimStatus::InvalidState,
+            "NoMemory" => RMakerClaimStatus::NoMemory,
+            _ => Self::default(),
+        }
+    }
+}
+
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct PayloadBuf {
+    pub Offset: u32,
+    pub Payload: Vec<u8>,
+    pub TotalLen: u32,
+}
+
+impl<'a> MessageRead<'a> for PayloadBuf {
+    fn from_reader(r: &mut BytesReader, bytes: &'a [u8]) -> Result<Self> {
+        let mut msg = Self::default();
+        while !r.is_eof() {
+            match r.next_tag(bytes) {
+                Ok(8) => msg.Offset = r.read_uint32(bytes)?,
+                Ok(18) => msg.Payload = r.read_bytes(bytes)?.to_owned(),
+                Ok(24) => msg.TotalLen = r.read_uint32(bytes)?,
+                Ok(t) => { r.read_unknown(bytes, t)?; }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(msg)
+    }
+}
+
+impl MessageWrite for PayloadBuf {
+    fn get_size(&self) -> usize {
+        0
+        + if self.Offset == 0u32 { 0 } else { 1 + sizeof_varint(*(&self.Offset) as u64) }
+        + if self.Payload.is_empty() { 0 } else { 1 + sizeof_len((&self.Payload).len()) }
+        + if self.TotalLen == 0u32 { 0 } else { 1 + sizeof_varint(*(&self.TotalLen) as u64) }
+    }
+
+    fn write_message<W: WriterBackend>(&self, w: &mut Writer<W>) -> Result<()> {
+        if self.Offset != 0u32 { w.write_with_tag(8, |w| w.write_uint32(*&self.Offset))?; }
+        if !self.Payload.is_empty() { w.write_with_tag(18, |w| w.write_bytes(&**&self.Payload))?; }
+        if self.TotalLen != 0u32 { w.write_with_tag(24, |w| w.write_uint32(*&self.TotalLen))?; }
+        Ok(())
+    }
+}
+
+
+            impl TryFrom<&[u8]> for PayloadBuf {
+                type Error=quick_protobuf::Error;
+
+                fn try_from(buf: &[u8]) -> Result<Self> {
+                    let mut reader = BytesReader::from_bytes(&buf);
+                    Ok(PayloadBuf::from_reader(&mut reader, &buf)?)
+                }
+            }
+            
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct RespPayload {
+    pub Status: RMakerClaimStatus,
+    pub Buf: Option<PayloadBuf>,
+}
+
+impl<'a> MessageRead<'a> for RespPayload {
+    fn from_reader(r: &mut BytesReader, bytes: &'a [u8]) -> Result<Self> {
+        let mut msg = Self::default();
+        while !r.is_eof() {
+            match r.next_tag(bytes) {
+                Ok(8) => msg.Status = r.read_enum(bytes)?,
+                Ok(18) => msg.Buf = Some(r.read_message::<PayloadBuf>(bytes)?),
+                Ok(t) => { r.read_unknown(bytes, t)?; }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(msg)
+    }
+}
+
+impl MessageWrite for RespPayload {
+    fn get_size(&self) -> usize {
+        0
+        + if self.Status == RMakerClaimStatus::Success { 0 } else { 1 + sizeof_varint(*(&self.Status) as u64) }
+        + self.Buf.as_ref().map_or(0, |m| 1 + sizeof_len((m).get_size()))
+    }
+
+    fn write_message<W: WriterBackend>(&self, w: &mut Writer<W>) -> Result<()> {
+        if self.Status != RMakerClaimStatus::Success { w.write_with_tag(8, |w| w.write_enum(*&self.Status as i32))?; }
+        if let Some(ref s) = self.Buf { w.write_with_tag(18, |w| w.write_message(s))?; }
+        Ok(())
+    }
+}
+
+
+            impl TryFrom<&[u8]> for RespPayload {
+                type Error=quick_protobuf::Error;
+
+                fn try_from(buf: &[u8]) -> Result<Self> {
+                    let mut reader = BytesReader::from_bytes(&buf);
+                    Ok(RespPayload::from_reader(&mut reader, &buf)?)
+                }
+            }
+            
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RMakerClaimMsgType {
+    TypeCmdClaimStart = 0,
+    TypeRespClaimStart = 1,
+    TypeCmdClaimInit = 2,
+    TypeRespClaimInit = 3,
+    TypeCmdClaimVerify = 4,
+    TypeRespClaimVerify = 5,
+    TypeCmdClaimAbort = 6,
+    TypeRespClaimAbort = 7,
+}
+
+impl Default for RMakerClaimMsgType {
+    fn default() -> Self {
+        RMakerClaimMsgType::TypeCmdClaimStart
+    }
+}
+
+impl From<i32> for RMakerClaimMsgType {
+    fn from(i: i32) -> Self {
+        match i {
+            0 => RMakerClaimMsgType::TypeCmdClaimStart,
+            1 => RMakerClaimMsgType::TypeRespClaimStart,
+            2 => RMakerClaimMsgType::TypeCmdClaimInit,
+            3 => RMakerClaimMsgType::TypeRespClaimInit,
+            4 => RMakerClaimMsgType::TypeCmdClaimVerify,
+            5 => RMakerClaimMsgType::TypeRespClaimVerify,
+            6 => RMakerClaimMsgType::TypeCmdClaimAbort,
+            7 => RMakerClaimMsgType::TypeRespClaimAbort,
+            _ => Self::default(),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for RMakerClaimMsgType {
+    fn from(s: &'a str) -> Self {
+        match s {
+            "TypeCmdClaimStart" => RMakerClaimMsgType::TypeCmdClaimStart,
+            "TypeRespClaimStart" => RMakerClaimMsgType::TypeRespClaimStart,
+            "TypeCmdClaimInit" => RMakerClaimMsgType::TypeCmdClaimInit,
+            "TypeRespClaimInit" => RMakerClaimMsgType::TypeRespClaimInit,
+            "TypeCmdClaimVerify" => RMakerClaimMsgType::TypeCmdClaimVerify,
+            "TypeRespClaimVerify" => RMakerClaimMsgType::TypeRespClaimVerify,
+            "TypeCmdClaimAbort" => RMakerClaimMsgType::TypeCmdClaimAbort,
+            "TypeRespClaimAbort" => RMakerClaimMsgType::TypeRespClaimAbort,
+            _ => Self::default(),
+        }
+    }
+}
+
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct RMakerClaimPayload {
+    pub msg: RMakerClaimMsgType,
+    pub payload: mod_RMakerClaimPayload::OneOfpayload,
+}
+
+impl<'a> MessageRead<'a> for RMakerClaimPayload {
+    fn from_reader(r: &mut BytesReader, bytes: &'a [u8]) -> Result<Self> {
+        let mut msg = Self::default();
+        while !r.is_eof() {
+            match r.next_tag(bytes) {
+                Ok(8) => msg.msg = r.read_enum(bytes)?,
+                Ok(82) => msg.payload = mod_RMakerClaimPayload::OneOfpayload::cmdPayload(r.read_message::<PayloadBuf>(bytes)?),
+                Ok(90) => msg.payload = mod_RMakerClaimPayload::OneOfpayload::respPayload(r.read_message::<RespPayload>(bytes)?),
+                Ok(t) => { r.read_unknown(bytes, t)?; }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(msg)
+    }
+}
+
+impl MessageWrite for RMakerClaimPayload {
+    fn get_size(&self) -> usize {
+        0
+        + if self.msg == RMakerClaimMsgType::TypeCmdClaimStart { 0 } else { 1 + sizeof_varint(*(&self.msg) as u64) }
+        + match self.payload {
+            mod_RMakerClaimPayload::OneOfpayload::cmdPayload(ref m) => 1 + sizeof_len((m).get_size()),
+            mod_RMakerClaimPayload::OneOfpayload::respPayload(ref m) => 1 + sizeof_len((m).get_size()),
+            mod_RMakerClaimPayload::OneOfpayload::None => 0,
+    }    }
+
+    fn write_message<W: WriterBackend>(&self, w: &mut Writer<W>) -> Result<()> {
+        if self.msg != RMakerClaimMsgType::TypeCmdClaimStart { w.write_with_tag(8, |w| w.write_enum(*&self.msg as i32))?; }
+        match self.payload {            mod_RMakerClaimPayload::OneOfpayload::cmdPayload(ref m) => { w.write_with_tag(82, |w| w.write_message(m))? },
+            mod_RMakerClaimPayload::OneOfpayload::respPayload(ref m) => { w.write_with_tag(90, |w| w.write_message(m))? },
+            mod_RMakerClaimPayload::OneOfpayload::None => {},
+    }        Ok(())
+    }
+}
+
+
+            impl TryFrom<&[u8]> for RMakerClaimPayload {
+                type Error=quick_protobuf::Error;
+
+                fn try_from(buf: &[u8]) -> Result<Self> {
+                    let mut reader = BytesReader::from_bytes(&buf);
+                    Ok(RMakerClaimPayload::from_reader(&mut reader, &buf)?)
+                }
+            }
+            
+pub mod mod_RMakerClaimPayload {
+
+use super::*;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum OneOfpayload {
+    cmdPayload(PayloadBuf),
+    respPayload(RespPayload),
+    None,
+}
+
+impl Default for OneOfpayload {
+    fn default() -> Self {
+        OneOfpayload::None
+    }
+}
+
+}
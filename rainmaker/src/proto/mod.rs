@@ -1 +1,5 @@
+#[cfg(feature = "local-ctrl")]
+pub mod esp_local_ctrl;
+#[cfg(feature = "assisted-claim")]
+pub mod esp_rmaker_claim;
 pub mod esp_rmaker_user_mapping;
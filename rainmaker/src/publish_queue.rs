@@ -0,0 +1,440 @@
+use std::collections::VecDeque;
+
+use crate::{
+    constants::{NODE_CONFIG_TOPIC_SUFFIX, NODE_PARAMS_LOCAL_INIT_TOPIC_SUFFIX},
+    utils::{get_nvs_namespace, hashed_nvs_key},
+};
+
+const PUBLISH_QUEUE_NVS_NAMESPACE: &str = "rmaker_pubq";
+// upper limit on the stored queue so that it fits in a single NVS blob
+const PUBLISH_QUEUE_MAX_STORED_SIZE: usize = 16 * 1024;
+
+type QueueEntry = (String, Vec<u8>); // (topic, payload)
+
+/// Configuration of the queue holding messages published while the agent is offline.
+///
+/// Messages are published in the order they were queued once the connection is restored and are
+/// removed from the queue only after they are handed over to the MQTT client. If publishing a
+/// message fails, it and the messages after it stay queued till the next connection.
+#[derive(Debug, Clone)]
+pub struct PublishQueueConfig {
+    /// Maximum number of queued messages. Oldest message is dropped when the queue is full.
+    pub capacity: usize,
+    /// Topics(relative to `node/<node_id>/`) on which only the latest message matters. A new
+    /// message on such topic replaces the queued one instead of being added to the queue.
+    ///
+    /// Defaults to the node configuration and initial parameter values. Messages on all the
+    /// other topics, e.g. parameter updates, are never collapsed.
+    pub coalesce_topics: Vec<String>,
+    /// Stores the queue in the `nvs` partition, so that messages survive a reboot.
+    ///
+    /// The queue is rewritten on every change, so this should be enabled only if the storage can
+    /// handle the write rate. Each node has its own stored queue. Only the newest messages are
+    /// stored if the queue does not fit in a single NVS entry(16KB).
+    pub persist: bool,
+}
+
+impl Default for PublishQueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 32,
+            coalesce_topics: vec![
+                NODE_CONFIG_TOPIC_SUFFIX.to_string(),
+                NODE_PARAMS_LOCAL_INIT_TOPIC_SUFFIX.to_string(),
+            ],
+            persist: false,
+        }
+    }
+}
+
+/// Statistics of the offline publish queue, see [`Rainmaker::publish_queue_stats`].
+///
+/// [`Rainmaker::publish_queue_stats`]: crate::Rainmaker::publish_queue_stats
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PublishQueueStats {
+    /// Number of messages currently queued.
+    pub queued: usize,
+    /// Number of queued messages published after the connection was restored.
+    pub published: u64,
+    /// Number of queued messages replaced by a newer message on the same topic.
+    pub coalesced: u64,
+    /// Number of messages dropped because the queue was full.
+    pub dropped: u64,
+}
+
+pub(crate) struct PublishQueue {
+    config: PublishQueueConfig,
+    // key of the stored queue, derived from the node id
+    nvs_key: String,
+    entries: VecDeque<QueueEntry>,
+    stats: PublishQueueStats,
+}
+
+impl PublishQueue {
+    pub(crate) fn new(config: PublishQueueConfig, node_id: &str) -> Self {
+        let nvs_key = hashed_nvs_key("q", node_id);
+        let entries = if config.persist {
+            load_entries(&nvs_key)
+        } else {
+            VecDeque::new()
+        };
+        if !entries.is_empty() {
+            log::info!("restored {} queued message(s)", entries.len());
+        }
+
+        Self::with_entries(config, nvs_key, entries)
+    }
+
+    // restored queue may be larger than the capacity if it was lowered since it was stored
+    fn with_entries(
+        config: PublishQueueConfig,
+        nvs_key: String,
+        mut entries: VecDeque<QueueEntry>,
+    ) -> Self {
+        let excess = entries.len().saturating_sub(config.capacity);
+        entries.drain(..excess);
+
+        let mut queue = Self {
+            config,
+            nvs_key,
+            entries,
+            stats: PublishQueueStats::default(),
+        };
+        if excess > 0 {
+            log::warn!(
+                "dropped {} restored message(s) exceeding the capacity",
+                excess
+            );
+            queue.stats.dropped = excess as u64;
+            queue.on_change();
+        }
+        queue.stats.queued = queue.entries.len();
+        queue
+    }
+
+    /// Adds a message to the queue. Returns topic of the message dropped to make room for it.
+    pub(crate) fn push(&mut self, topic: &str, payload: Vec<u8>) -> Option<String> {
+        if self.config.capacity == 0 {
+            self.stats.dropped += 1;
+            return Some(topic.to_owned());
+        }
+
+        let mut dropped = None;
+        let coalesce = self.is_coalesced(topic);
+        match self
+            .entries
+            .iter_mut()
+            .find(|(t, _)| coalesce && t == topic)
+        {
+            Some(entry) => {
+                entry.1 = payload;
+                self.stats.coalesced += 1;
+            }
+            None => {
+                if self.entries.len() >= self.config.capacity {
+                    dropped = self.entries.pop_front().map(|(topic, _)| topic);
+                    self.stats.dropped += 1;
+                }
+                self.entries.push_back((topic.to_owned(), payload));
+            }
+        }
+
+        self.on_change();
+        dropped
+    }
+
+    /// Publishes the queued messages using `publish`, in the order they were queued. Each message
+    /// is removed only after it is published.
+    ///
+    /// Stops at the first message which could not be published, so that it and the messages after
+    /// it stay queued. Returns topic of that message.
+    pub(crate) fn flush<E>(
+        &mut self,
+        mut publish: impl FnMut(&str, Vec<u8>) -> Result<(), E>,
+    ) -> Option<String> {
+        let mut failed_topic = None;
+        let mut published = 0;
+        while let Some((topic, payload)) = self.entries.front() {
+            if publish(topic, payload.clone()).is_err() {
+                failed_topic = Some(topic.clone());
+                break;
+            }
+            self.entries.pop_front();
+            published += 1;
+        }
+
+        if published > 0 {
+            self.stats.published += published;
+            self.on_change();
+        }
+        failed_topic
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub(crate) fn stats(&self) -> PublishQueueStats {
+        self.stats
+    }
+
+    fn is_coalesced(&self, topic: &str) -> bool {
+        self.config.coalesce_topics.iter().any(|suffix| {
+            topic
+                .strip_suffix(suffix.as_str())
+                .is_some_and(|prefix| prefix.ends_with('/'))
+        })
+    }
+
+    fn on_change(&mut self) {
+        self.stats.queued = self.entries.len();
+        if self.config.persist {
+            store_entries(&self.nvs_key, &self.entries);
+        }
+    }
+}
+
+fn store_entries(nvs_key: &str, entries: &VecDeque<QueueEntry>) {
+    let mut nvs = match get_nvs_namespace(PUBLISH_QUEUE_NVS_NAMESPACE) {
+        Some(nvs) => nvs,
+        None => return,
+    };
+
+    let buff = encode_entries(entries);
+    if nvs.set_bytes(nvs_key, &buff).is_err() {
+        log::error!("could not store publish queue");
+        // previously stored queue must not be replayed on the next boot
+        if nvs.set_bytes(nvs_key, &[]).is_err() {
+            log::error!("could not erase stored publish queue");
+        }
+    }
+}
+
+fn load_entries(nvs_key: &str) -> VecDeque<QueueEntry> {
+    let nvs = match get_nvs_namespace(PUBLISH_QUEUE_NVS_NAMESPACE) {
+        Some(nvs) => nvs,
+        None => return VecDeque::new(),
+    };
+
+    let mut buff = vec![0u8; PUBLISH_QUEUE_MAX_STORED_SIZE];
+    match nvs.get_bytes(nvs_key, &mut buff) {
+        Ok(Some(bytes)) => decode_entries(&bytes),
+        _ => VecDeque::new(),
+    }
+}
+
+// entries are stored as: topic length(u16 LE), topic, payload length(u32 LE), payload
+fn encode_entries(entries: &VecDeque<QueueEntry>) -> Vec<u8> {
+    // newest entries are kept if the whole queue does not fit
+    let mut size = 0;
+    let stored = entries
+        .iter()
+        .rev()
+        .take_while(|(topic, payload)| {
+            size += 6 + topic.len() + payload.len();
+            size <= PUBLISH_QUEUE_MAX_STORED_SIZE
+        })
+        .count();
+    if stored < entries.len() {
+        log::warn!(
+            "publish queue too large to store. storing newest {} message(s)",
+            stored
+        );
+    }
+
+    let mut buff = vec![];
+    for (topic, payload) in entries.iter().skip(entries.len() - stored) {
+        buff.extend_from_slice(&(topic.len() as u16).to_le_bytes());
+        buff.extend_from_slice(topic.as_bytes());
+        buff.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        buff.extend_from_slice(payload);
+    }
+    buff
+}
+
+fn decode_entries(mut bytes: &[u8]) -> VecDeque<QueueEntry> {
+    let mut entries = VecDeque::new();
+    while !bytes.is_empty() {
+        match parse_entry(bytes) {
+            Some((entry, next)) => {
+                entries.push_back(entry);
+                bytes = next;
+            }
+            None => {
+                log::error!("stored publish queue is corrupted, ignoring remaining messages");
+                break;
+            }
+        }
+    }
+
+    entries
+}
+
+fn parse_entry(bytes: &[u8]) -> Option<(QueueEntry, &[u8])> {
+    let (len, rest) = bytes.split_first_chunk::<2>()?;
+    let len = u16::from_le_bytes(*len) as usize;
+    let topic = String::from_utf8(rest.get(..len)?.to_vec()).ok()?;
+    let rest = &rest[len..];
+
+    let (len, rest) = rest.split_first_chunk::<4>()?;
+    let len = u32::from_le_bytes(*len) as usize;
+    let payload = rest.get(..len)?.to_vec();
+
+    Some(((topic, payload), &rest[len..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(capacity: usize) -> PublishQueue {
+        let config = PublishQueueConfig {
+            capacity,
+            ..Default::default()
+        };
+        PublishQueue::new(config, "test-node")
+    }
+
+    fn entry(topic: &str, payload: &str) -> QueueEntry {
+        (topic.to_owned(), payload.as_bytes().to_vec())
+    }
+
+    fn topics(queue: &PublishQueue) -> Vec<&str> {
+        queue.entries.iter().map(|(t, _)| t.as_str()).collect()
+    }
+
+    #[test]
+    fn coalesced_topic_replaces_queued_message() {
+        let mut queue = queue(8);
+        queue.push("node/n/config", b"old".to_vec());
+        queue.push("node/n/params/local", b"1".to_vec());
+        queue.push("node/n/config", b"new".to_vec());
+
+        assert_eq!(
+            queue.entries,
+            [
+                entry("node/n/config", "new"),
+                entry("node/n/params/local", "1")
+            ]
+        );
+        assert_eq!(queue.stats().coalesced, 1);
+        assert_eq!(queue.stats().queued, 2);
+    }
+
+    #[test]
+    fn other_topics_are_not_coalesced() {
+        let mut queue = queue(8);
+        queue.push("node/n/params/local", b"1".to_vec());
+        queue.push("node/n/params/local", b"2".to_vec());
+        // suffix of a coalesced topic, but a different level
+        queue.push("node/n/myconfig", b"1".to_vec());
+        queue.push("node/n/myconfig", b"2".to_vec());
+
+        assert_eq!(queue.entries.len(), 4);
+        assert_eq!(queue.stats().coalesced, 0);
+    }
+
+    #[test]
+    fn oldest_message_is_dropped_when_full() {
+        let mut queue = queue(2);
+        assert_eq!(queue.push("a", vec![]), None);
+        assert_eq!(queue.push("b", vec![]), None);
+        assert_eq!(queue.push("c", vec![]), Some("a".to_owned()));
+
+        assert_eq!(topics(&queue), ["b", "c"]);
+        assert_eq!(queue.stats().dropped, 1);
+    }
+
+    #[test]
+    fn nothing_is_queued_without_capacity() {
+        let mut queue = queue(0);
+        assert_eq!(queue.push("a", vec![]), Some("a".to_owned()));
+        assert!(queue.is_empty());
+        assert_eq!(queue.stats().dropped, 1);
+    }
+
+    #[test]
+    fn flush_keeps_failed_message_and_the_ones_after_it() {
+        let mut queue = queue(8);
+        for topic in ["a", "b", "c"] {
+            queue.push(topic, vec![]);
+        }
+
+        let mut published = vec![];
+        let failed = queue.flush(|topic, _| match topic {
+            "b" => Err(()),
+            _ => {
+                published.push(topic.to_owned());
+                Ok(())
+            }
+        });
+        assert_eq!(failed, Some("b".to_owned()));
+        assert_eq!(published, ["a"]);
+        assert_eq!(topics(&queue), ["b", "c"]);
+        assert_eq!(queue.stats().published, 1);
+
+        let mut published = vec![];
+        let failed = queue.flush(|topic, _| {
+            published.push(topic.to_owned());
+            Ok::<_, ()>(())
+        });
+        assert_eq!(failed, None);
+        assert_eq!(published, ["b", "c"]);
+        assert!(queue.is_empty());
+        assert_eq!(queue.stats().published, 3);
+    }
+
+    #[test]
+    fn restored_queue_is_trimmed_to_capacity() {
+        let config = PublishQueueConfig {
+            capacity: 2,
+            ..Default::default()
+        };
+        let entries = VecDeque::from([entry("a", "1"), entry("b", "2"), entry("c", "3")]);
+        let queue = PublishQueue::with_entries(config, "q".to_owned(), entries);
+
+        assert_eq!(topics(&queue), ["b", "c"]);
+        assert_eq!(queue.stats().dropped, 1);
+        assert_eq!(queue.stats().queued, 2);
+    }
+
+    #[test]
+    fn stored_entries_round_trip() {
+        let entries = VecDeque::from([
+            entry("node/n/params/local", r#"{"Light":{"Power":true}}"#),
+            entry("node/n/alert", ""),
+        ]);
+        assert_eq!(decode_entries(&encode_entries(&entries)), entries);
+        assert!(decode_entries(&encode_entries(&VecDeque::new())).is_empty());
+    }
+
+    #[test]
+    fn newest_entries_are_stored_if_queue_does_not_fit() {
+        let large = "x".repeat(PUBLISH_QUEUE_MAX_STORED_SIZE / 2);
+        let entries = VecDeque::from([entry("a", &large), entry("b", &large), entry("c", "")]);
+
+        let stored = encode_entries(&entries);
+        assert!(stored.len() <= PUBLISH_QUEUE_MAX_STORED_SIZE);
+        assert_eq!(
+            decode_entries(&stored),
+            [entry("b", &large), entry("c", "")]
+        );
+    }
+
+    #[test]
+    fn corrupted_entries_are_ignored() {
+        let entries = VecDeque::from([entry("a", "1"), entry("b", "2")]);
+        let stored = encode_entries(&entries);
+
+        // truncated in the payload and in the length of the second entry
+        for len in [stored.len() - 1, 9, 10] {
+            assert_eq!(decode_entries(&stored[..len]), [entry("a", "1")]);
+        }
+        // topic length beyond the blob
+        assert!(decode_entries(&[0xff, 0xff, b'a']).is_empty());
+        // invalid UTF-8 topic
+        let mut invalid = stored.clone();
+        invalid[2] = 0xff;
+        assert!(decode_entries(&invalid).is_empty());
+    }
+}
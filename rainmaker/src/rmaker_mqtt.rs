@@ -1,177 +1,544 @@
 use std::{
     collections::HashMap,
-    sync::{atomic::AtomicBool, LazyLock, OnceLock, RwLock},
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, RwLock, Weak,
+    },
 };
 
-use rainmaker_components::mqtt::{
-    MqttClient, MqttConfiguration, MqttEvent, QoSLevel, ReceivedMessage, TLSconfiguration,
-};
+use rainmaker_components::{mqtt::ReceivedMessage, persistent_storage::NvsPartition};
 
-use crate::{error::RmakerMqttError, factory, utils::wrap_in_arc_mutex, WrappedInArcMutex};
+use crate::{
+    error::RmakerMqttError,
+    publish_queue::{PublishQueue, PublishQueueConfig, PublishQueueStats},
+    transport::{QoS, Transport, TransportEvent},
+};
 
 pub(crate) trait TopicCb = Fn(ReceivedMessage) + Sync + Send + 'static;
-static MQTT_INNER: OnceLock<WrappedInArcMutex<MqttClient>> = OnceLock::new();
-static MQTT_CBS: LazyLock<RwLock<HashMap<String, Box<dyn TopicCb>>>> =
-    LazyLock::new(|| RwLock::new(HashMap::new()));
-static PUBLISH_QUEUE: LazyLock<RwLock<HashMap<String, Vec<u8>>>> =
-    LazyLock::new(|| RwLock::new(HashMap::new())); // topic -> payload
-static CONNECTED: AtomicBool = AtomicBool::new(false);
-
-pub(crate) fn init_rmaker_mqtt() -> Result<(), RmakerMqttError> {
-    // return error if mqtt is already initialized
-    if is_mqtt_initialized() {
-        return Err(RmakerMqttError::AlreadyInitialized);
-    }
+pub(crate) type TopicCbType = Box<dyn Fn(ReceivedMessage) + Send + Sync + 'static>;
+type TopicHandlers = Vec<(SubscriptionId, Arc<dyn TopicCb>)>;
+pub(crate) type ConnectionCbType = Box<dyn Fn(&ConnectionEvent) + Send + Sync + 'static>;
+type SharedConnectionCb = Arc<dyn Fn(&ConnectionEvent) + Send + Sync + 'static>;
+type ConnectWaiter = Box<dyn FnOnce() + Send>;
 
-    let mut buff = [0u8; 2500];
-
-    let node_id = match factory::get_node_id(&mut buff) {
-        Ok(node_id) => node_id,
-        Err(_) => return Err(RmakerMqttError::NodeCredentialsNotFound),
-    };
-    let mut client_cert = match factory::get_client_cert(&mut buff) {
-        Ok(cert) => cert,
-        Err(_) => return Err(RmakerMqttError::NodeCredentialsNotFound),
-    };
-    let mut private_key = match factory::get_client_key(&mut buff) {
-        Ok(key) => key,
-        Err(_) => return Err(RmakerMqttError::NodeCredentialsNotFound),
-    };
-    let mut server_cert = Vec::from(include_bytes!("../server_certs/rmaker_mqtt_server.crt"));
-
-    client_cert.push(0);
-    private_key.push(0);
-    server_cert.push(0);
+/// Events related to the connection of an agent to the RainMaker cloud.
+///
+/// Registered using [`Rainmaker::register_connection_callback`](crate::Rainmaker::register_connection_callback).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConnectionEvent {
+    /// Connected to the MQTT broker. Subscriptions are restored and queued messages are published.
+    Connected,
+    /// Connection to the MQTT broker is lost or closed.
+    Disconnected,
+    /// Connection is lost(or could not be established) and the client is retrying.
+    Reconnecting,
+    /// Subscribing to the topic failed.
+    SubscribeFailed(String),
+    /// Publishing a message on the topic failed.
+    PublishFailed(String),
+}
 
-    let mqtt_tls_config = TLSconfiguration {
-        // temporary workaround
-        client_cert: Box::leak(Box::new(client_cert)),
-        private_key: Box::leak(Box::new(private_key)),
-        server_cert: Box::leak(Box::new(server_cert)),
-    };
+/// Identifies a topic handler registered using [`Rainmaker::subscribe`](crate::Rainmaker::subscribe).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
 
-    connect(
-        &MqttConfiguration {
-            host: "a1p72mufdu6064-ats.iot.us-east-1.amazonaws.com",
-            // host: "127.0.0.1",
-            clientid: node_id.as_str(),
-            port: 8883,
-            // port: 1883,
-        },
-        Box::leak(Box::new(mqtt_tls_config)),
-    )?;
-
-    // self.mqtt_client = Some(Arc::new(Mutex::new(mqtt_client)));
-    Ok(())
+/// MQTT connection of an agent to the RainMaker cloud, over a [Transport].
+///
+/// Cloning returns another handle to the same connection. Subscriptions are restored and queued
+/// messages are published on every (re)connection.
+#[derive(Clone)]
+pub(crate) struct RmakerMqtt {
+    inner: Arc<MqttInner>,
 }
 
-pub(crate) fn is_mqtt_initialized() -> bool {
-    MQTT_INNER.get().is_some()
+struct MqttInner {
+    // taken out while connecting and disconnecting, as the transport may report events meanwhile
+    transport: Mutex<Option<Box<dyn Transport>>>,
+    initialized: AtomicBool,
+    // set if connected while the transport was taken out
+    pending_connect: AtomicBool,
+    // handlers of each topic filter, the filter is subscribed while it has any handler
+    callbacks: RwLock<HashMap<String, TopicHandlers>>,
+    next_subscription_id: AtomicU64,
+    publish_queue: Mutex<PublishQueue>,
+    connected: AtomicBool,
+    // notified once, after the queued messages are flushed
+    connect_waiters: Mutex<Vec<ConnectWaiter>>,
+    event_callbacks: RwLock<Vec<SharedConnectionCb>>,
 }
 
-// this function is not used right now but may be required in future
-#[allow(dead_code)]
-pub(crate) fn is_mqtt_connected() -> bool {
-    CONNECTED.load(std::sync::atomic::Ordering::SeqCst)
+impl Debug for RmakerMqtt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RmakerMqtt")
+            .field("initialized", &self.is_initialized())
+            .field("connected", &self.is_connected())
+            .finish()
+    }
 }
 
-fn mqtt_callback(event: MqttEvent) {
-    match event {
-        MqttEvent::Received(msg) => {
-            let topic = &msg.topic;
-            let topic_cbs = MQTT_CBS.read().unwrap();
-            if let Some(callback) = topic_cbs.get(topic) {
-                callback(msg)
+impl RmakerMqtt {
+    pub(crate) fn new(
+        node_id: &str,
+        transport: Box<dyn Transport>,
+        queue_config: PublishQueueConfig,
+    ) -> Self {
+        Self {
+            inner: Arc::new(MqttInner {
+                transport: Mutex::new(Some(transport)),
+                initialized: AtomicBool::new(false),
+                pending_connect: AtomicBool::new(false),
+                callbacks: RwLock::new(HashMap::new()),
+                next_subscription_id: AtomicU64::new(0),
+                publish_queue: Mutex::new(PublishQueue::new(queue_config, node_id)),
+                connected: AtomicBool::new(false),
+                connect_waiters: Mutex::new(Vec::new()),
+                event_callbacks: RwLock::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Connects to the MQTT broker using the node credentials stored in the factory partition.
+    pub(crate) fn init(&self, factory_partition: &NvsPartition) -> Result<(), RmakerMqttError> {
+        let mut transport = {
+            let mut transport = self.inner.transport.lock().unwrap();
+            // return error if mqtt is already initialized
+            if self.inner.initialized.swap(true, Ordering::SeqCst) {
+                return Err(RmakerMqttError::AlreadyInitialized);
+            }
+            match transport.take() {
+                Some(transport) => transport,
+                None => {
+                    // still being disconnected
+                    self.inner.initialized.store(false, Ordering::SeqCst);
+                    return Err(RmakerMqttError::OtherError);
+                }
             }
+        };
+        self.inner.pending_connect.store(false, Ordering::SeqCst);
+
+        // events only hold a weak reference so that dropping the agent drops the connection
+        let inner = Arc::downgrade(&self.inner);
+        let res = transport.connect(
+            factory_partition,
+            Box::new(move |event| transport_callback(&inner, event)),
+        );
+        *self.inner.transport.lock().unwrap() = Some(transport);
+        if let Err(e) = res {
+            self.inner.initialized.store(false, Ordering::SeqCst);
+            self.inner.connected.store(false, Ordering::SeqCst);
+            return Err(e);
         }
 
-        MqttEvent::Connected => {
-            CONNECTED.store(true, std::sync::atomic::Ordering::SeqCst);
-            let mut mqtt = MQTT_INNER.get().unwrap().lock().unwrap();
-            for topic in MQTT_CBS.read().unwrap().keys() {
-                if mqtt.subscribe(topic, &QoSLevel::AtLeastOnce).is_err() {
-                    log::error!("could not subscribe to {}", topic)
-                };
+        if self.inner.pending_connect.swap(false, Ordering::SeqCst) {
+            self.inner.handle_connected();
+        }
+
+        Ok(())
+    }
+
+    /// Disconnects from the MQTT broker and drops all the subscriptions.
+    ///
+    /// Queued messages are handed over to the transport before disconnecting if connected,
+    /// otherwise they are kept and published on the next connection. The connection can be
+    /// initialized again afterwards.
+    pub(crate) fn deinit(&self) {
+        // transport is disconnected without holding the lock as connection events may still be
+        // delivered
+        let mut transport = {
+            let mut transport = self.inner.transport.lock().unwrap();
+            if !self.inner.initialized.swap(false, Ordering::SeqCst) {
+                return;
             }
-            for (topic, payload) in PUBLISH_QUEUE.read().unwrap().iter() {
-                mqtt.publish(topic, &QoSLevel::AtLeastOnce, payload.to_vec());
+            match transport.take() {
+                Some(transport) => transport,
+                None => return,
             }
+        };
+
+        let mut failed_topic = None;
+        if self.is_connected() {
+            let mut queue = self.inner.publish_queue.lock().unwrap();
+            failed_topic = flush_queue(&mut queue, transport.as_mut());
         }
+        // subscriptions are not persisted by the broker, so dropping the callbacks is enough
+        self.inner.callbacks.write().unwrap().clear();
+        // waiters are dropped without being notified
+        self.inner.connect_waiters.lock().unwrap().clear();
+        log::info!("disconnecting from MQTT broker");
+        transport.disconnect();
+        *self.inner.transport.lock().unwrap() = Some(transport);
 
-        MqttEvent::Disconnected => {
-            CONNECTED.store(false, std::sync::atomic::Ordering::SeqCst);
+        if let Some(topic) = failed_topic {
+            self.inner.emit(ConnectionEvent::PublishFailed(topic));
+        }
+        if self.inner.connected.swap(false, Ordering::SeqCst) {
+            self.inner.emit(ConnectionEvent::Disconnected);
         }
+    }
 
-        _ => {}
+    pub(crate) fn is_initialized(&self) -> bool {
+        self.inner.initialized.load(Ordering::SeqCst)
     }
-}
 
-pub(crate) fn connect(
-    config: &MqttConfiguration,
-    tls_config: &'static TLSconfiguration,
-) -> Result<(), RmakerMqttError> {
-    if is_mqtt_initialized() {
-        return Err(RmakerMqttError::AlreadyInitialized);
+    pub(crate) fn is_connected(&self) -> bool {
+        self.inner.connected.load(Ordering::SeqCst)
     }
 
-    if let Ok(mqtt_client) = MqttClient::new(config, tls_config, Box::new(mqtt_callback)) {
-        return match MQTT_INNER.set(wrap_in_arc_mutex(mqtt_client)) {
-            Ok(_) => Ok(()),
-            // This error should never occur
-            Err(_) => unreachable!(),
-        };
+    pub(crate) fn publish_queue_stats(&self) -> PublishQueueStats {
+        self.inner.publish_queue.lock().unwrap().stats()
     }
 
-    Err(RmakerMqttError::OtherError)
-}
+    /// Registers a callback for [ConnectionEvent]s. Callbacks are kept across reinitialization.
+    pub(crate) fn register_event_callback(&self, cb: ConnectionCbType) {
+        self.inner
+            .event_callbacks
+            .write()
+            .unwrap()
+            .push(Arc::from(cb));
+    }
 
-pub(crate) fn publish(topic: &str, payload: Vec<u8>) -> Result<(), RmakerMqttError> {
-    match MQTT_INNER.get() {
-        Some(client) => {
-            if CONNECTED.load(std::sync::atomic::Ordering::SeqCst) {
-                client
-                    .lock()
-                    .unwrap()
-                    .publish(topic, &QoSLevel::AtLeastOnce, payload);
-            } else {
-                // mqtt is not connected. store to publish when connected
-                log::info!("mqtt not connected. queueing message");
-                PUBLISH_QUEUE
-                    .write()
-                    .unwrap()
-                    .insert(topic.to_owned(), payload);
-            }
-        }
-        None => {
-            return Err(RmakerMqttError::AlreadyInitialized);
+    /// Executes `waiter` once connected to the MQTT broker, right away if already connected.
+    ///
+    /// `waiter` is dropped without being executed if the connection is deinitialized first.
+    #[cfg(feature = "async")]
+    pub(crate) fn on_connected(&self, waiter: impl FnOnce() + Send + 'static) {
+        let mut waiters = self.inner.connect_waiters.lock().unwrap();
+        if self.is_connected() {
+            drop(waiters);
+            waiter();
+        } else {
+            waiters.push(Box::new(waiter));
         }
-    };
+    }
 
-    Ok(())
-}
+    /// Publishes a message, or queues it if not connected.
+    pub(crate) fn publish(&self, topic: &str, payload: Vec<u8>) -> Result<(), RmakerMqttError> {
+        match self.try_publish(topic, payload) {
+            Ok(None) => {}
+            Ok(Some(dropped_topic)) => {
+                log::warn!("publish queue full. dropped message on {}", dropped_topic);
+                self.inner
+                    .emit(ConnectionEvent::PublishFailed(dropped_topic));
+            }
+            Err(e) => {
+                self.inner
+                    .emit(ConnectionEvent::PublishFailed(topic.to_owned()));
+                return Err(e);
+            }
+        }
 
-pub(crate) fn subscribe(topic: &str, cb: impl TopicCb) -> Result<(), RmakerMqttError> {
-    match MQTT_INNER.get() {
-        Some(client) => {
-            if CONNECTED.load(std::sync::atomic::Ordering::SeqCst)
-                && client
-                    .lock()
-                    .unwrap()
-                    .subscribe(topic, &QoSLevel::AtLeastOnce)
-                    .is_err()
-            {
-                return Err(RmakerMqttError::OtherError);
+        Ok(())
+    }
+
+    // returns topic of the queued message dropped to make room for this one, if any
+    fn try_publish(
+        &self,
+        topic: &str,
+        payload: Vec<u8>,
+    ) -> Result<Option<String>, RmakerMqttError> {
+        if !self.is_initialized() {
+            return Err(RmakerMqttError::NotInitialized);
+        }
+
+        // held while queueing so that the message is not missed by a flush on connection
+        let mut transport = self.inner.transport.lock().unwrap();
+        let mut queue = self.inner.publish_queue.lock().unwrap();
+        if let Some(transport) = transport.as_mut().filter(|_| self.is_connected()) {
+            // messages left queued by a failed flush are published first to keep the order
+            if queue.is_empty() || flush_queue(&mut queue, transport.as_mut()).is_none() {
+                transport.publish(topic, QoS::AtLeastOnce, payload)?;
+                return Ok(None);
             }
+        } else {
+            log::info!("mqtt not connected. queueing message");
+        }
 
-            MQTT_CBS
-                .write()
-                .unwrap()
-                .insert(topic.to_owned(), Box::new(cb));
+        // store to publish when connected
+        Ok(queue.push(topic, payload))
+    }
+
+    /// Registers a handler for the messages on `topic`, which can be an MQTT topic filter with
+    /// `+` and `#` wildcards. Multiple handlers can be registered for the same filter, the filter
+    /// is subscribed only once.
+    pub(crate) fn subscribe(
+        &self,
+        topic: &str,
+        cb: impl TopicCb,
+    ) -> Result<SubscriptionId, RmakerMqttError> {
+        if !is_valid_topic_filter(topic) {
+            return Err(RmakerMqttError::InvalidTopic(topic.to_owned()));
         }
-        None => {
+        if !self.is_initialized() {
             return Err(RmakerMqttError::NotInitialized);
         }
+
+        // held till the handler is added so that the filter is not subscribed twice
+        let mut transport = self.inner.transport.lock().unwrap();
+        let is_new = !self.inner.callbacks.read().unwrap().contains_key(topic);
+        // subscription is made on the next connection if not connected
+        if let Some(transport) = transport.as_mut().filter(|_| is_new && self.is_connected()) {
+            if transport.subscribe(topic, QoS::AtLeastOnce).is_err() {
+                self.inner
+                    .emit(ConnectionEvent::SubscribeFailed(topic.to_owned()));
+                return Err(RmakerMqttError::OtherError);
+            }
+        }
+
+        let id = SubscriptionId(
+            self.inner
+                .next_subscription_id
+                .fetch_add(1, Ordering::SeqCst),
+        );
+        self.inner
+            .callbacks
+            .write()
+            .unwrap()
+            .entry(topic.to_owned())
+            .or_default()
+            .push((id, Arc::new(cb)));
+
+        Ok(id)
+    }
+
+    /// Removes a handler registered using [`subscribe`](RmakerMqtt::subscribe). The topic is
+    /// unsubscribed once it has no handlers.
+    ///
+    /// The handler is removed even if unsubscribing the topic fails.
+    pub(crate) fn unsubscribe(&self, id: SubscriptionId) -> Result<(), RmakerMqttError> {
+        let mut transport = self.inner.transport.lock().unwrap();
+        let topic = {
+            let mut callbacks = self.inner.callbacks.write().unwrap();
+            let (topic, handlers) = callbacks
+                .iter_mut()
+                .find(|(_, handlers)| handlers.iter().any(|(i, _)| *i == id))
+                .ok_or(RmakerMqttError::NotSubscribed)?;
+            handlers.retain(|(i, _)| *i != id);
+            if !handlers.is_empty() {
+                return Ok(());
+            }
+            let topic = topic.to_owned();
+            callbacks.remove(&topic);
+            topic
+        };
+
+        match transport.as_mut() {
+            Some(transport) if self.is_connected() => {
+                transport.unsubscribe(&topic).inspect_err(|_| {
+                    log::error!("could not unsubscribe from {}", topic);
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl MqttInner {
+    // restores the subscriptions and publishes the queued messages
+    fn handle_connected(&self) {
+        let (failed_subscriptions, failed_publish) = {
+            let mut transport = self.transport.lock().unwrap();
+            let transport = match transport.as_mut() {
+                Some(transport) => transport,
+                None => {
+                    // handled once the transport is connected
+                    self.pending_connect.store(true, Ordering::SeqCst);
+                    return;
+                }
+            };
+
+            let mut failed_subscriptions = vec![];
+            for topic in self.callbacks.read().unwrap().keys() {
+                if transport.subscribe(topic, QoS::AtLeastOnce).is_err() {
+                    log::error!("could not subscribe to {}", topic);
+                    failed_subscriptions.push(topic.to_owned());
+                };
+            }
+            let mut queue = self.publish_queue.lock().unwrap();
+            (
+                failed_subscriptions,
+                flush_queue(&mut queue, transport.as_mut()),
+            )
+        };
+
+        let waiters = std::mem::take(&mut *self.connect_waiters.lock().unwrap());
+        for waiter in waiters {
+            waiter();
+        }
+
+        self.emit(ConnectionEvent::Connected);
+        for topic in failed_subscriptions {
+            self.emit(ConnectionEvent::SubscribeFailed(topic));
+        }
+        if let Some(topic) = failed_publish {
+            self.emit(ConnectionEvent::PublishFailed(topic));
+        }
+    }
+
+    // callbacks are executed without holding any lock so that they can use the agent
+    fn emit(&self, event: ConnectionEvent) {
+        let callbacks = self.event_callbacks.read().unwrap().clone();
+        for callback in callbacks {
+            callback(&event);
+        }
+    }
+}
+
+// queued messages are removed once handed over, so they are never replayed. returns topic of the
+// message which could not be published, it stays queued along with the messages after it
+fn flush_queue(queue: &mut PublishQueue, transport: &mut dyn Transport) -> Option<String> {
+    let failed_topic =
+        queue.flush(|topic, payload| transport.publish(topic, QoS::AtLeastOnce, payload));
+    if let Some(topic) = &failed_topic {
+        log::error!(
+            "could not publish queued message on {}. keeping it queued",
+            topic
+        );
+    }
+
+    failed_topic
+}
+
+fn transport_callback(inner: &Weak<MqttInner>, event: TransportEvent) {
+    let inner = match inner.upgrade() {
+        Some(inner) => inner,
+        None => return,
     };
 
-    Ok(())
+    match event {
+        TransportEvent::Received { topic, payload } => {
+            // handlers of all the matching filters are executed in the order they were
+            // registered, without holding the lock so that they can use the connection
+            let mut handlers: TopicHandlers = inner
+                .callbacks
+                .read()
+                .unwrap()
+                .iter()
+                .filter(|(filter, _)| topic_matches(filter, &topic))
+                .flat_map(|(_, handlers)| handlers.iter().cloned())
+                .collect();
+            handlers.sort_by_key(|(id, _)| id.0);
+            for (_, callback) in handlers {
+                callback(ReceivedMessage {
+                    topic: topic.clone(),
+                    payload: payload.clone(),
+                })
+            }
+        }
+
+        TransportEvent::Connected => {
+            inner.connected.store(true, Ordering::SeqCst);
+            inner.handle_connected();
+        }
+
+        TransportEvent::Disconnected => {
+            if inner.connected.swap(false, Ordering::SeqCst) {
+                inner.emit(ConnectionEvent::Disconnected);
+            }
+            // transport keeps retrying till it is disconnected
+            if inner.initialized.load(Ordering::SeqCst) {
+                inner.emit(ConnectionEvent::Reconnecting);
+            }
+        }
+    }
+}
+
+// `+` must occupy a whole level and `#` must be the last level
+fn is_valid_topic_filter(filter: &str) -> bool {
+    let levels: Vec<&str> = filter.split('/').collect();
+    !filter.is_empty()
+        && levels.iter().enumerate().all(|(i, level)| match *level {
+            "#" => i == levels.len() - 1,
+            "+" => true,
+            level => !level.contains(['+', '#']),
+        })
+}
+
+/// Returns true if `topic` matches the topic filter, as per the MQTT specification.
+///
+/// `+` matches exactly one level and `#` matches any number of levels, including the parent
+/// level, e.g. `node/+/params/#` matches `node/abc/params` and `node/abc/params/local`.
+pub(crate) fn topic_matches(filter: &str, topic: &str) -> bool {
+    // topics starting with `$` are reserved and not matched by wildcards at the first level
+    if topic.starts_with('$') && filter.starts_with(['+', '#']) {
+        return false;
+    }
+
+    let mut topic_levels = topic.split('/');
+    for level in filter.split('/') {
+        match level {
+            "#" => return true,
+            "+" => {
+                if topic_levels.next().is_none() {
+                    return false;
+                }
+            }
+            level => {
+                if topic_levels.next() != Some(level) {
+                    return false;
+                }
+            }
+        }
+    }
+
+    topic_levels.next().is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_topic_filters() {
+        assert!(is_valid_topic_filter("node/abc/params/remote"));
+        assert!(is_valid_topic_filter("node/+/params/#"));
+        assert!(is_valid_topic_filter("#"));
+        assert!(is_valid_topic_filter("+"));
+        assert!(is_valid_topic_filter("a//b"));
+    }
+
+    #[test]
+    fn invalid_topic_filters() {
+        assert!(!is_valid_topic_filter(""));
+        assert!(!is_valid_topic_filter("a/#/b"));
+        assert!(!is_valid_topic_filter("a+/b"));
+        assert!(!is_valid_topic_filter("a/b#"));
+        assert!(!is_valid_topic_filter("a/#+"));
+    }
+
+    #[test]
+    fn exact_topic_matches() {
+        assert!(topic_matches("a/b", "a/b"));
+        assert!(!topic_matches("a/b", "a/c"));
+        assert!(!topic_matches("a/b", "a"));
+        assert!(!topic_matches("a/b", "a/b/c"));
+    }
+
+    #[test]
+    fn single_level_wildcard_matches_one_level() {
+        assert!(topic_matches("node/+/params", "node/abc/params"));
+        assert!(topic_matches("a/+", "a/"));
+        assert!(!topic_matches("a/+", "a"));
+        assert!(!topic_matches("a/+", "a/b/c"));
+        assert!(!topic_matches("node/+/params", "node/params"));
+    }
+
+    #[test]
+    fn multi_level_wildcard_matches_parent_level() {
+        assert!(topic_matches("a/#", "a"));
+        assert!(topic_matches("a/#", "a/b"));
+        assert!(topic_matches("a/#", "a/b/c"));
+        assert!(topic_matches("node/+/params/#", "node/abc/params"));
+        assert!(!topic_matches("a/#", "b/a"));
+        assert!(topic_matches("#", "a/b"));
+    }
+
+    #[test]
+    fn wildcards_do_not_match_reserved_topics() {
+        assert!(!topic_matches("#", "$SYS/broker"));
+        assert!(!topic_matches("+/broker", "$SYS/broker"));
+        assert!(topic_matches("$SYS/#", "$SYS/broker"));
+        assert!(topic_matches("$SYS/+", "$SYS/broker"));
+    }
 }
@@ -0,0 +1,552 @@
+//! Schedules service.
+//!
+//! Adds the standard `esp.service.schedule` service to the node. Schedules are created, edited,
+//! removed, enabled and disabled from the phone application by writing to the `Schedules`
+//! parameter. They are stored in NVS and executed on the node itself, so they keep working when
+//! the phone application is closed or the cloud is unreachable.
+//!
+//! A schedule triggers at a minute of the day(`m`) on the days set in the `d` bitmap(bit 0 for
+//! Monday to bit 6 for Sunday). A schedule with `d = 0` triggers only once and is disabled
+//! afterwards. Actions are executed through the device callbacks, just like writes received
+//! from the cloud.
+//!
+//! Schedules are executed as per the local time of the node, see [time](crate::time).
+//!
+//! Changes are accepted only once they are stored. Requests that would make the stored schedules
+//! exceed 4KB are rejected, and so is the whole write if the schedules could not be stored.
+//!
+//! Example:
+//! ```rust
+//! let mut rmaker = RainmakerBuilder::new(factory_partition)?
+//!     .node(node)
+//!     .schedules()
+//!     .build()?;
+//! rmaker.start()?;
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex, Weak,
+    },
+    thread,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    device::ParamWriteResult,
+    error::RmakerScheduleError,
+    node::Node,
+    param::{Param, ParamProperty, ParamTypes, ParamUi, ParamValue},
+    service::{Service, ServiceType},
+    time,
+    utils::get_nvs_namespace,
+};
+
+const SCHEDULE_SERVICE_NAME: &str = "Schedule";
+const SCHEDULE_PARAM_SCHEDULES: &str = "Schedules";
+const SCHEDULE_NVS_NAMESPACE: &str = "rmaker_sch";
+const SCHEDULE_NVS_KEY: &str = "schedules";
+const SCHEDULE_MAX_SCHEDULES: usize = 10;
+const SCHEDULE_MAX_SIZE: usize = 4096;
+
+// `{"device": {"param": value}}`, same as the writes received from the cloud
+type ScheduleAction = HashMap<String, HashMap<String, Value>>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Trigger {
+    /// Minutes since midnight.
+    m: u16,
+    /// Days of the week bitmap, bit 0 being Monday. 0 for one time schedules.
+    #[serde(default)]
+    d: u8,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Schedule {
+    id: String,
+    name: String,
+    enabled: bool,
+    triggers: Vec<Trigger>,
+    action: ScheduleAction,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    info: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    flags: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ScheduleOperation {
+    Add,
+    Edit,
+    Remove,
+    Enable,
+    Disable,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScheduleRequest {
+    id: String,
+    operation: ScheduleOperation,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    enabled: Option<bool>,
+    #[serde(default)]
+    triggers: Option<Vec<Trigger>>,
+    #[serde(default)]
+    action: Option<ScheduleAction>,
+    #[serde(default)]
+    info: Option<String>,
+    #[serde(default)]
+    flags: Option<u32>,
+}
+
+type SharedSchedules = Arc<Mutex<Vec<Schedule>>>;
+
+/// Schedules of an agent and the task executing them.
+pub(crate) struct Scheduler {
+    schedules: SharedSchedules,
+    // dropping the sender stops the task
+    stop_tx: Mutex<Option<Sender<()>>>,
+}
+
+impl Scheduler {
+    /// Loads stored schedules.
+    pub(crate) fn new() -> Self {
+        let schedules = load_schedules();
+        log::info!("loaded {} schedule(s)", schedules.len());
+
+        Self {
+            schedules: Arc::new(Mutex::new(schedules)),
+            stop_tx: Mutex::new(None),
+        }
+    }
+
+    /// Returns the schedule service to be added to the node.
+    pub(crate) fn service(&self) -> Service {
+        let schedules = self.schedules.lock().unwrap();
+
+        let mut schedule_service = Service::new(SCHEDULE_SERVICE_NAME, ServiceType::Schedule);
+        let mut schedules_param = Param::new(
+            SCHEDULE_PARAM_SCHEDULES,
+            ParamValue::Array(to_json_array(&schedules)),
+            ParamTypes::Schedules,
+            HashSet::from([ParamProperty::Read, ParamProperty::Write]),
+            ParamUi::Hidden,
+        );
+        schedules_param.add_bounds(0, SCHEDULE_MAX_SCHEDULES as i32, 1);
+        schedule_service.add_param(schedules_param);
+
+        let shared_schedules = self.schedules.clone();
+        schedule_service.register_callback(Box::new(move |params| {
+            schedule_service_cb(&shared_schedules, params)
+        }));
+
+        schedule_service
+    }
+
+    /// Starts the thread which executes the schedules.
+    ///
+    /// The thread exits once the scheduler is stopped or the node is dropped.
+    pub(crate) fn start(&self, node: Weak<Mutex<Node>>) -> Result<(), RmakerScheduleError> {
+        let mut stop_tx = self.stop_tx.lock().unwrap();
+        if stop_tx.is_some() {
+            return Ok(());
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let schedules = self.schedules.clone();
+        thread::Builder::new()
+            .name("rmaker_schedule".to_string())
+            .spawn(move || scheduler_task(schedules, node, rx))
+            .map_err(|_| RmakerScheduleError::TaskSpawnFailed)?;
+        *stop_tx = Some(tx);
+
+        Ok(())
+    }
+
+    /// Stops the thread executing the schedules. Schedules are kept and executed again once the
+    /// scheduler is restarted.
+    pub(crate) fn stop(&self) {
+        self.stop_tx.lock().unwrap().take();
+    }
+}
+
+fn schedule_service_cb(
+    schedules: &SharedSchedules,
+    params: HashMap<String, ParamValue>,
+) -> ParamWriteResult {
+    let mut result = ParamWriteResult::new();
+    let requests = match params.get(SCHEDULE_PARAM_SCHEDULES) {
+        Some(ParamValue::Array(requests)) => requests,
+        _ => return result,
+    };
+
+    let mut schedules = schedules.lock().unwrap();
+    let previous = schedules.clone();
+    for request in requests {
+        let request = match serde_json::from_value::<ScheduleRequest>(request.clone()) {
+            Ok(request) => request,
+            Err(e) => {
+                log::error!("invalid schedule request {}: {}", request, e);
+                continue;
+            }
+        };
+        if let Err(e) = apply_request(&mut schedules, request) {
+            log::error!("could not apply schedule request: {}", e);
+        }
+    }
+    // schedules reported to the cloud must survive a reboot
+    if let Err(e) = store_schedules(&schedules) {
+        log::error!("{}, discarding the changes", e);
+        *schedules = previous;
+        result.reject(SCHEDULE_PARAM_SCHEDULES);
+        return result;
+    }
+
+    result.accept(
+        SCHEDULE_PARAM_SCHEDULES,
+        ParamValue::Array(to_json_array(&schedules)),
+    );
+    result
+}
+
+/// Applies the request to the schedules. The schedules are left unchanged if the request is
+/// invalid or the resulting schedules would not fit in the storage.
+fn apply_request(
+    schedules: &mut Vec<Schedule>,
+    request: ScheduleRequest,
+) -> Result<(), RmakerScheduleError> {
+    let id = request.id.clone();
+    let mut updated = schedules.clone();
+    apply_operation(&mut updated, request)?;
+    if serialize_schedules(&updated)?.len() > SCHEDULE_MAX_SIZE {
+        return Err(RmakerScheduleError::TooLarge(id));
+    }
+    *schedules = updated;
+
+    Ok(())
+}
+
+fn apply_operation(
+    schedules: &mut Vec<Schedule>,
+    request: ScheduleRequest,
+) -> Result<(), RmakerScheduleError> {
+    let index = schedules.iter().position(|s| s.id == request.id);
+
+    match request.operation {
+        ScheduleOperation::Add => {
+            if index.is_some() {
+                return Err(RmakerScheduleError::AlreadyExists(request.id));
+            }
+            if schedules.len() >= SCHEDULE_MAX_SCHEDULES {
+                return Err(RmakerScheduleError::LimitReached);
+            }
+            let (triggers, action) = match (request.triggers, request.action) {
+                (Some(triggers), Some(action)) if !triggers.is_empty() => (triggers, action),
+                _ => return Err(RmakerScheduleError::InvalidRequest(request.id)),
+            };
+            log::info!("adding schedule {}", request.id);
+            schedules.push(Schedule {
+                name: request.name.unwrap_or_else(|| request.id.clone()),
+                id: request.id,
+                enabled: request.enabled.unwrap_or(true),
+                triggers,
+                action,
+                info: request.info,
+                flags: request.flags,
+            });
+        }
+        ScheduleOperation::Edit => {
+            let index = index.ok_or(RmakerScheduleError::NotFound(request.id.clone()))?;
+            let schedule = &mut schedules[index];
+            log::info!("editing schedule {}", request.id);
+            if let Some(name) = request.name {
+                schedule.name = name;
+            }
+            if let Some(triggers) = request.triggers.filter(|t| !t.is_empty()) {
+                schedule.triggers = triggers;
+            }
+            if let Some(action) = request.action {
+                schedule.action = action;
+            }
+            if let Some(enabled) = request.enabled {
+                schedule.enabled = enabled;
+            }
+            if request.info.is_some() {
+                schedule.info = request.info;
+            }
+            if request.flags.is_some() {
+                schedule.flags = request.flags;
+            }
+        }
+        ScheduleOperation::Remove => {
+            let index = index.ok_or(RmakerScheduleError::NotFound(request.id.clone()))?;
+            log::info!("removing schedule {}", request.id);
+            schedules.remove(index);
+        }
+        ScheduleOperation::Enable | ScheduleOperation::Disable => {
+            let index = index.ok_or(RmakerScheduleError::NotFound(request.id.clone()))?;
+            // "enable" carries the new state in `enabled`, same as the C SDK
+            let enabled = match request.operation {
+                ScheduleOperation::Disable => false,
+                _ => request.enabled.unwrap_or(true),
+            };
+            log::info!("schedule {} enabled: {}", request.id, enabled);
+            schedules[index].enabled = enabled;
+        }
+    }
+
+    Ok(())
+}
+
+fn scheduler_task(schedules: SharedSchedules, node: Weak<Mutex<Node>>, stop_rx: Receiver<()>) {
+    let mut last_checked = None;
+
+    loop {
+        let timeout = if let Some(now) = time::local_now() {
+            let current = (now.weekday, now.minute_of_day());
+            if last_checked != Some(current) {
+                last_checked = Some(current);
+                let node = match node.upgrade() {
+                    Some(node) => node,
+                    None => return,
+                };
+                run_due_schedules(&schedules, &node, current.0, current.1);
+            }
+            // wake up at the start of next minute
+            Duration::from_secs(60 - now.second.min(59) as u64)
+        } else {
+            // time is not available yet
+            Duration::from_secs(10)
+        };
+
+        if stop_rx.recv_timeout(timeout) != Err(RecvTimeoutError::Timeout) {
+            log::info!("scheduler stopped");
+            return;
+        }
+    }
+}
+
+fn run_due_schedules(
+    shared_schedules: &SharedSchedules,
+    node: &Mutex<Node>,
+    weekday: u8,
+    minute: u16,
+) {
+    let (actions, one_time_finished) = {
+        let mut schedules = shared_schedules.lock().unwrap();
+        let (actions, one_time_finished) = trigger_due_schedules(&mut schedules, weekday, minute);
+        if one_time_finished {
+            if let Err(e) = store_schedules(&schedules) {
+                log::error!("{}", e);
+            }
+        }
+        (actions, one_time_finished)
+    };
+
+    for action in actions {
+        let payload = match serde_json::to_vec(&action) {
+            Ok(payload) => payload,
+            Err(_) => continue,
+        };
+        if let Err(e) = crate::handle_params_update(&payload, node) {
+            log::error!("could not execute schedule action: {}", e);
+        }
+    }
+
+    if one_time_finished {
+        let schedules = to_json_array(&shared_schedules.lock().unwrap());
+        if let Err(e) = node.lock().unwrap().update_param(
+            SCHEDULE_SERVICE_NAME,
+            SCHEDULE_PARAM_SCHEDULES,
+            ParamValue::Array(schedules),
+        ) {
+            log::error!("could not report schedules: {}", e);
+        }
+    }
+}
+
+/// Returns the actions of the enabled schedules due at `minute` of `weekday`(0 for Monday).
+/// One time schedules are disabled once triggered, in which case true is returned as well.
+fn trigger_due_schedules(
+    schedules: &mut [Schedule],
+    weekday: u8,
+    minute: u16,
+) -> (Vec<ScheduleAction>, bool) {
+    let mut actions = vec![];
+    let mut one_time_finished = false;
+
+    for schedule in schedules.iter_mut().filter(|s| s.enabled) {
+        let due = schedule
+            .triggers
+            .iter()
+            .any(|t| t.m == minute && (t.d == 0 || t.d & (1 << weekday) != 0));
+        if !due {
+            continue;
+        }
+
+        log::info!("triggering schedule {}", schedule.id);
+        actions.push(schedule.action.clone());
+        if schedule.triggers.iter().all(|t| t.d == 0) {
+            schedule.enabled = false;
+            one_time_finished = true;
+        }
+    }
+
+    (actions, one_time_finished)
+}
+
+fn to_json_array(schedules: &[Schedule]) -> Vec<Value> {
+    schedules
+        .iter()
+        .filter_map(|s| serde_json::to_value(s).ok())
+        .collect()
+}
+
+fn load_schedules() -> Vec<Schedule> {
+    let nvs = match get_nvs_namespace(SCHEDULE_NVS_NAMESPACE) {
+        Some(nvs) => nvs,
+        None => return vec![],
+    };
+
+    let mut buff = vec![0u8; SCHEDULE_MAX_SIZE];
+    match nvs.get_bytes(SCHEDULE_NVS_KEY, &mut buff) {
+        Ok(Some(bytes)) => serde_json::from_slice(&bytes).unwrap_or_default(),
+        _ => vec![],
+    }
+}
+
+fn serialize_schedules(schedules: &[Schedule]) -> Result<Vec<u8>, RmakerScheduleError> {
+    serde_json::to_vec(schedules).map_err(|_| RmakerScheduleError::StoreFailed)
+}
+
+fn store_schedules(schedules: &[Schedule]) -> Result<(), RmakerScheduleError> {
+    let mut nvs =
+        get_nvs_namespace(SCHEDULE_NVS_NAMESPACE).ok_or(RmakerScheduleError::StoreFailed)?;
+
+    let schedules = serialize_schedules(schedules)?;
+    if schedules.len() > SCHEDULE_MAX_SIZE {
+        return Err(RmakerScheduleError::StoreFailed);
+    }
+    nvs.set_bytes(SCHEDULE_NVS_KEY, &schedules)
+        .map_err(|_| RmakerScheduleError::StoreFailed)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn request(value: Value) -> ScheduleRequest {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn add_request(id: &str, d: u8) -> ScheduleRequest {
+        request(json!({
+            "id": id,
+            "operation": "add",
+            "triggers": [{"m": 480, "d": d}],
+            "action": {"Light": {"Power": true}},
+        }))
+    }
+
+    fn schedules_with(requests: Vec<ScheduleRequest>) -> Vec<Schedule> {
+        let mut schedules = vec![];
+        for request in requests {
+            apply_request(&mut schedules, request).unwrap();
+        }
+        schedules
+    }
+
+    #[test]
+    fn weekly_schedule_triggers_on_set_days() {
+        // Monday and Wednesday
+        let mut schedules = schedules_with(vec![add_request("s1", 0b101)]);
+
+        for (weekday, due) in [(0, true), (1, false), (2, true), (6, false)] {
+            let (actions, one_time_finished) = trigger_due_schedules(&mut schedules, weekday, 480);
+            assert_eq!(actions.len(), due as usize, "weekday {}", weekday);
+            assert!(!one_time_finished);
+        }
+        assert!(schedules[0].enabled);
+    }
+
+    #[test]
+    fn schedule_triggers_only_at_its_minute() {
+        let mut schedules = schedules_with(vec![add_request("s1", 0x7f)]);
+
+        assert!(trigger_due_schedules(&mut schedules, 3, 479).0.is_empty());
+        assert!(trigger_due_schedules(&mut schedules, 3, 481).0.is_empty());
+        let (actions, _) = trigger_due_schedules(&mut schedules, 3, 480);
+        assert_eq!(actions, vec![schedules[0].action.clone()]);
+    }
+
+    #[test]
+    fn one_time_schedule_is_disabled_after_triggering() {
+        let mut schedules = schedules_with(vec![add_request("once", 0), add_request("s2", 0x7f)]);
+
+        let (actions, one_time_finished) = trigger_due_schedules(&mut schedules, 4, 480);
+        assert_eq!(actions.len(), 2);
+        assert!(one_time_finished);
+        assert!(!schedules[0].enabled);
+        assert!(schedules[1].enabled);
+
+        let (actions, one_time_finished) = trigger_due_schedules(&mut schedules, 5, 480);
+        assert_eq!(actions.len(), 1);
+        assert!(!one_time_finished);
+    }
+
+    #[test]
+    fn disabled_schedule_does_not_trigger() {
+        let mut schedules = schedules_with(vec![
+            add_request("s1", 0x7f),
+            request(json!({"id": "s1", "operation": "disable"})),
+        ]);
+
+        assert!(trigger_due_schedules(&mut schedules, 0, 480).0.is_empty());
+    }
+
+    #[test]
+    fn add_is_refused_at_the_limit() {
+        let mut schedules = schedules_with(
+            (0..SCHEDULE_MAX_SCHEDULES)
+                .map(|i| add_request(&format!("s{}", i), 0x7f))
+                .collect(),
+        );
+
+        assert!(matches!(
+            apply_request(&mut schedules, add_request("extra", 0x7f)),
+            Err(RmakerScheduleError::LimitReached)
+        ));
+        assert_eq!(schedules.len(), SCHEDULE_MAX_SCHEDULES);
+    }
+
+    #[test]
+    fn add_and_edit_are_refused_if_schedules_do_not_fit() {
+        let mut schedules = schedules_with(vec![add_request("s1", 0x7f)]);
+        let info = "x".repeat(SCHEDULE_MAX_SIZE);
+
+        let mut large_add = add_request("s2", 0x7f);
+        large_add.info = Some(info.clone());
+        assert!(matches!(
+            apply_request(&mut schedules, large_add),
+            Err(RmakerScheduleError::TooLarge(_))
+        ));
+        assert_eq!(schedules.len(), 1);
+
+        let large_edit = request(json!({"id": "s1", "operation": "edit", "info": info}));
+        assert!(matches!(
+            apply_request(&mut schedules, large_edit),
+            Err(RmakerScheduleError::TooLarge(_))
+        ));
+        assert_eq!(schedules[0].info, None);
+    }
+}
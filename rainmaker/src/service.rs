@@ -0,0 +1,121 @@
+//! Service module.
+//!
+//! Services are entities very similar to devices in terms of structure. The main difference is
+//! that services are meant for operations which user may not need to interact with directly,
+//! like OTA firmware updates, schedules, timezone, etc.
+//!
+//! Services are reported in the `services` array of the node configuration and their parameters
+//! are updated over the same `params/remote` and `params/local` topics as device parameters.
+//!
+//! Standard services are enabled using their respective methods on [RainmakerBuilder], e.g.
+//! [ota].
+//!
+//! [RainmakerBuilder]: crate::RainmakerBuilder
+//! [ota]: crate::RainmakerBuilder::ota
+
+use std::{collections::HashMap, fmt::Debug, sync::Arc};
+
+use serde::Serialize;
+
+use crate::{
+    device::{DeviceCbType, SharedDeviceCb},
+    param::Param,
+};
+
+#[derive(Serialize)]
+pub struct Service {
+    name: String,
+    #[serde(rename = "type")]
+    service_type: ServiceType,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    attributes: HashMap<String, String>,
+    params: Vec<Param>,
+    #[serde(skip_serializing)]
+    callback: Option<SharedDeviceCb>,
+}
+
+impl Debug for Service {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Service")
+            .field("name", &self.name)
+            .field("service_type", &self.service_type)
+            .field("attributes", &self.attributes)
+            .field("params", &self.params)
+            .finish()
+    }
+}
+
+impl Service {
+    /// This function creates an instance of service.
+    pub fn new(name: &str, service_type: ServiceType) -> Self {
+        Self {
+            name: name.to_owned(),
+            service_type,
+            attributes: Default::default(),
+            params: vec![],
+            callback: None,
+        }
+    }
+
+    pub fn add_attribute(&mut self, name: String, value: String) {
+        self.attributes.insert(name, value);
+    }
+
+    /// This function associates a parameter with the service.
+    pub fn add_param(&mut self, param: Param) {
+        self.params.push(param);
+    }
+
+    /// Removes a parameter from the service and returns it.
+    pub fn remove_param(&mut self, param_name: &str) -> Option<Param> {
+        let index = self.params.iter().position(|p| p.name() == param_name)?;
+        Some(self.params.remove(index))
+    }
+
+    /// This function associates a callback which is executed when parameters of the service are updated.
+    pub fn register_callback(&mut self, cb: DeviceCbType) {
+        self.callback = Some(Arc::from(cb));
+    }
+
+    /// Returns name of the service.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns list of parameters of the service.
+    pub fn params(&self) -> &[Param] {
+        &self.params
+    }
+
+    /// Returns parameter with the given name.
+    pub fn param(&self, param_name: &str) -> Option<&Param> {
+        self.params.iter().find(|p| p.name() == param_name)
+    }
+
+    pub(crate) fn params_mut(&mut self) -> &mut [Param] {
+        &mut self.params
+    }
+
+    pub(crate) fn param_mut(&mut self, param_name: &str) -> Option<&mut Param> {
+        self.params.iter_mut().find(|p| p.name() == param_name)
+    }
+
+    pub(crate) fn callback(&self) -> Option<SharedDeviceCb> {
+        self.callback.clone()
+    }
+}
+
+/// Set of standard services supported by ESP RainMaker.
+#[derive(Debug, Serialize)]
+pub enum ServiceType {
+    #[serde(rename = "esp.service.ota")]
+    Ota,
+    #[serde(rename = "esp.service.schedule")]
+    Schedule,
+    #[serde(rename = "esp.service.time")]
+    Time,
+    #[serde(rename = "esp.service.system")]
+    System,
+    #[serde(rename = "esp.service.local_control")]
+    LocalControl,
+}
@@ -0,0 +1,298 @@
+//! System service.
+//!
+//! Adds the standard `esp.service.system` service to the node which allows performing
+//! maintenance operations on the node remotely from the phone application or dashboard:
+//! - `Reboot`: Reboots the node.
+//! - `Factory-Reset`: Erases the `nvs` partition and reboots the node. The `fctry` partition,
+//!   which holds the node credentials, is preserved.
+//! - `Wi-Fi-Reset`: Clears only the Wi-Fi credentials stored by Wi-Fi provisioning and reboots
+//!   the node, so that provisioning starts again.
+//!
+//! Wi-Fi credentials are stored by the provisioning manager of the application(e.g.
+//! `WifiProvMgr`), so `Wi-Fi-Reset` is available only if the application provides a
+//! [WifiProvisioning] handler which clears them. The reset is verified using
+//! [`WifiProvisioning::is_provisioned`] before rebooting.
+//!
+//! Operations are performed after [`SystemServiceConfig::reboot_delay`] so that the response
+//! can be reported back to the cloud before the node goes down.
+//!
+//! On Linux, rebooting restarts the agent process and Wi-Fi is expected to be managed by the OS,
+//! so `Wi-Fi-Reset` only runs the handler and restarts the agent.
+//!
+//! Example:
+//! ```rust
+//! let mut rmaker = RainmakerBuilder::new(factory_partition)?
+//!     .node(node)
+//!     .system_service(SystemServiceConfig {
+//!         wifi_reset: Some(Arc::new(MyWifiProvisioning::new())),
+//!         ..Default::default()
+//!     })
+//!     .build()?;
+//! rmaker.start()?;
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    device::ParamWriteResult,
+    error::RmakerSystemError,
+    param::{Param, ParamProperty, ParamTypes, ParamUi, ParamValue},
+    service::{Service, ServiceType},
+    utils::NVS_PARTITION_NAME,
+};
+
+const SYSTEM_SERVICE_NAME: &str = "System";
+const SYSTEM_PARAM_REBOOT: &str = "Reboot";
+const SYSTEM_PARAM_FACTORY_RESET: &str = "Factory-Reset";
+const SYSTEM_PARAM_WIFI_RESET: &str = "Wi-Fi-Reset";
+
+/// Wi-Fi provisioning state of the application, used for `Wi-Fi-Reset`.
+///
+/// Implemented using the provisioning manager which stores the credentials, e.g. `WifiProvMgr`.
+/// Called from the system service task.
+pub trait WifiProvisioning: Send + Sync + 'static {
+    /// Clears the Wi-Fi credentials stored by provisioning.
+    fn reset(&self) -> Result<(), String>;
+    /// Returns true if Wi-Fi credentials are stored, e.g. `WifiProvMgr::is_provisioned`
+    /// returning `Some`.
+    fn is_provisioned(&self) -> bool;
+}
+
+/// Configuration for the system service.
+#[derive(Clone)]
+pub struct SystemServiceConfig {
+    pub reboot: bool,
+    pub factory_reset: bool,
+    /// Enables `Wi-Fi-Reset` using the handler. Disabled by default.
+    pub wifi_reset: Option<Arc<dyn WifiProvisioning>>,
+    /// Delay after which the requested operation is performed.
+    pub reboot_delay: Duration,
+}
+
+impl Debug for SystemServiceConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SystemServiceConfig")
+            .field("reboot", &self.reboot)
+            .field("factory_reset", &self.factory_reset)
+            .field("wifi_reset", &self.wifi_reset.is_some())
+            .field("reboot_delay", &self.reboot_delay)
+            .finish()
+    }
+}
+
+impl Default for SystemServiceConfig {
+    fn default() -> Self {
+        Self {
+            reboot: true,
+            factory_reset: true,
+            wifi_reset: None,
+            reboot_delay: Duration::from_secs(2),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum SystemOperation {
+    Reboot,
+    FactoryReset,
+    WifiReset,
+}
+
+// state shared by the callback of the service, owned by the node of the agent
+struct SystemState {
+    reboot_delay: Duration,
+    wifi_provisioning: Option<Arc<dyn WifiProvisioning>>,
+    // reboot affects the whole device, so only one operation can be pending at a time
+    operation_pending: AtomicBool,
+}
+
+/// Returns the system service to be added to the node.
+pub(crate) fn init(config: SystemServiceConfig) -> Service {
+    let mut system_service = Service::new(SYSTEM_SERVICE_NAME, ServiceType::System);
+    let params = [
+        (config.reboot, SYSTEM_PARAM_REBOOT, ParamTypes::Reboot),
+        (
+            config.factory_reset,
+            SYSTEM_PARAM_FACTORY_RESET,
+            ParamTypes::FactoryReset,
+        ),
+        (
+            config.wifi_reset.is_some(),
+            SYSTEM_PARAM_WIFI_RESET,
+            ParamTypes::WiFiReset,
+        ),
+    ];
+    for (_, name, param_type) in params.into_iter().filter(|(enabled, ..)| *enabled) {
+        system_service.add_param(Param::new(
+            name,
+            ParamValue::Bool(false),
+            param_type,
+            HashSet::from([ParamProperty::Read, ParamProperty::Write]),
+            ParamUi::Hidden,
+        ));
+    }
+
+    let state = Arc::new(SystemState {
+        reboot_delay: config.reboot_delay,
+        wifi_provisioning: config.wifi_reset,
+        operation_pending: AtomicBool::new(false),
+    });
+    system_service.register_callback(Box::new(move |params| system_service_cb(&state, params)));
+
+    system_service
+}
+
+fn system_service_cb(
+    state: &Arc<SystemState>,
+    params: HashMap<String, ParamValue>,
+) -> ParamWriteResult {
+    let mut result = ParamWriteResult::new();
+
+    // most destructive operation wins if multiple are requested together
+    let mut operation = None;
+    for (name, op) in [
+        (SYSTEM_PARAM_REBOOT, SystemOperation::Reboot),
+        (SYSTEM_PARAM_WIFI_RESET, SystemOperation::WifiReset),
+        (SYSTEM_PARAM_FACTORY_RESET, SystemOperation::FactoryReset),
+    ] {
+        match params.get(name) {
+            Some(ParamValue::Bool(true)) => {
+                // value is reported as true to acknowledge the request
+                result.accept(name, ParamValue::Bool(true));
+                operation = Some(op);
+            }
+            Some(_) => {
+                result.accept(name, ParamValue::Bool(false));
+            }
+            None => {}
+        }
+    }
+
+    if let Some(operation) = operation {
+        if let Err(e) = schedule_operation(state, operation) {
+            log::error!("could not perform system operation: {}", e);
+        }
+    }
+
+    result
+}
+
+fn schedule_operation(
+    state: &Arc<SystemState>,
+    operation: SystemOperation,
+) -> Result<(), RmakerSystemError> {
+    if state.operation_pending.swap(true, Ordering::SeqCst) {
+        return Err(RmakerSystemError::OperationPending);
+    }
+
+    log::info!("performing {:?} in {:?}", operation, state.reboot_delay);
+    let state = state.clone();
+    thread::Builder::new()
+        .name("rmaker_system".to_string())
+        .spawn(move || {
+            thread::sleep(state.reboot_delay);
+
+            let res = match (operation, &state.wifi_provisioning) {
+                (SystemOperation::Reboot, _) => Ok(()),
+                (SystemOperation::FactoryReset, _) => factory_reset(),
+                (SystemOperation::WifiReset, Some(wifi_provisioning)) => {
+                    wifi_reset(wifi_provisioning.as_ref())
+                }
+                // param is added only along with the handler
+                (SystemOperation::WifiReset, None) => Ok(()),
+            };
+            if let Err(e) = res {
+                log::error!("{:?} failed: {}", operation, e);
+            }
+
+            reboot();
+        })
+        .map_err(|_| RmakerSystemError::TaskSpawnFailed)?;
+
+    Ok(())
+}
+
+#[cfg(target_os = "espidf")]
+fn reboot() {
+    log::info!("rebooting");
+    unsafe { esp_idf_svc::sys::esp_restart() };
+}
+
+#[cfg(target_os = "linux")]
+fn reboot() {
+    use std::os::unix::process::CommandExt;
+
+    log::info!("restarting agent");
+    // exec only returns on failure
+    let err = std::env::current_exe()
+        .map(|exe| {
+            std::process::Command::new(exe)
+                .args(std::env::args_os().skip(1))
+                .exec()
+        })
+        .unwrap_or_else(|e| e);
+    log::error!("could not restart agent: {}", err);
+    std::process::exit(1);
+}
+
+#[cfg(target_os = "espidf")]
+fn factory_reset() -> Result<(), RmakerSystemError> {
+    use esp_idf_svc::sys::{esp, nvs_flash_erase_partition};
+
+    log::info!("erasing {} partition", NVS_PARTITION_NAME);
+    let partition = std::ffi::CString::new(NVS_PARTITION_NAME).unwrap();
+    esp!(unsafe { nvs_flash_erase_partition(partition.as_ptr()) })
+        .map_err(|e| RmakerSystemError::FactoryResetFailed(e.to_string()))
+}
+
+#[cfg(target_os = "linux")]
+fn factory_reset() -> Result<(), RmakerSystemError> {
+    let nvs_dir = crate::utils::nvs_partition_dir(NVS_PARTITION_NAME).ok_or_else(|| {
+        RmakerSystemError::FactoryResetFailed("could not locate nvs partition".to_string())
+    })?;
+
+    log::info!("erasing {}", nvs_dir.display());
+    std::fs::remove_dir_all(&nvs_dir)
+        .and_then(|_| std::fs::create_dir_all(&nvs_dir))
+        .map_err(|e| RmakerSystemError::FactoryResetFailed(e.to_string()))
+}
+
+fn wifi_reset(wifi_provisioning: &dyn WifiProvisioning) -> Result<(), RmakerSystemError> {
+    log::info!("clearing Wi-Fi credentials");
+    wifi_provisioning
+        .reset()
+        .map_err(RmakerSystemError::WifiResetFailed)?;
+    restore_wifi_driver()?;
+
+    if wifi_provisioning.is_provisioned() {
+        return Err(RmakerSystemError::WifiResetFailed(
+            "Wi-Fi credentials are still stored".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+// credentials stored by the Wi-Fi driver itself are cleared as well
+#[cfg(target_os = "espidf")]
+fn restore_wifi_driver() -> Result<(), RmakerSystemError> {
+    use esp_idf_svc::sys::{esp, esp_wifi_restore};
+
+    esp!(unsafe { esp_wifi_restore() })
+        .map_err(|e| RmakerSystemError::WifiResetFailed(e.to_string()))
+}
+
+#[cfg(target_os = "linux")]
+fn restore_wifi_driver() -> Result<(), RmakerSystemError> {
+    log::info!("Wi-Fi is managed by the OS, nothing to restore");
+    Ok(())
+}
@@ -0,0 +1,364 @@
+//! Support for testing applications built on the agent without the RainMaker cloud.
+//!
+//! [MockCloud] stands in for the MQTT broker of the RainMaker cloud. Agents built using
+//! [`MockCloud::builder`] connect to it through a [MockTransport] instead of the network, so the
+//! tests can run offline. The mock captures all the messages published by the node(config, params, user mapping, etc.),
+//! delivers messages to the topics subscribed by the node(e.g. `params/remote` writes) and can
+//! simulate connection loss.
+//!
+//! This module is available with the `test-util` feature.
+//!
+//! Example:
+//! ```rust
+//! let cloud = MockCloud::new();
+//! let mut rmaker = cloud
+//!     .builder(NvsPartition::new("fctry")?, "test-node")
+//!     .node(node)
+//!     .build()?;
+//! rmaker.start()?;
+//! assert!(cloud.last_json("config").is_some());
+//!
+//! assert!(cloud.write_params(json!({"Switch": {"Power": true}})));
+//! assert_eq!(
+//!     cloud.last_json("params/local"),
+//!     Some(json!({"Switch": {"Power": true}}))
+//! );
+//!
+//! assert!(send_user_mapping(&rmaker, "user-id", "secret-key"));
+//! assert!(cloud.last_json("user/mapping").is_some());
+//! ```
+
+use std::{
+    fmt::Debug,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use quick_protobuf::{MessageWrite, Writer};
+use rainmaker_components::persistent_storage::NvsPartition;
+use serde_json::Value;
+
+use crate::{
+    cloud_user_assoc_callback,
+    constants::*,
+    error::RmakerMqttError,
+    proto::esp_rmaker_user_mapping::*,
+    rmaker_mqtt::topic_matches,
+    transport::{QoS, Transport, TransportEvent, TransportEventCb},
+    Rainmaker, RainmakerBuilder,
+};
+
+type MockEventCb = Arc<TransportEventCb>;
+
+#[derive(Default)]
+struct MockState {
+    node_id: String,
+    online: bool,
+    published: Vec<(String, Vec<u8>)>,
+    subscriptions: Vec<String>,
+    // set while an agent is connected to the mock
+    event_cb: Option<MockEventCb>,
+}
+
+#[derive(Default)]
+struct MockShared {
+    state: Mutex<MockState>,
+    published: Condvar,
+}
+
+/// In-process stand-in for the RainMaker cloud, see [test_util](crate::test_util).
+///
+/// Cloning returns another handle to the same mock.
+#[derive(Clone)]
+pub struct MockCloud {
+    shared: Arc<MockShared>,
+}
+
+impl Debug for MockCloud {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.state();
+        f.debug_struct("MockCloud")
+            .field("node_id", &state.node_id)
+            .field("online", &state.online)
+            .field("published", &state.published.len())
+            .finish()
+    }
+}
+
+impl Default for MockCloud {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockCloud {
+    /// Creates a mock which is online, i.e. agents connect to it as soon as they are started.
+    pub fn new() -> Self {
+        let shared = MockShared::default();
+        shared.state.lock().unwrap().online = true;
+        Self {
+            shared: Arc::new(shared),
+        }
+    }
+
+    /// Creates a builder for an agent connected to this mock.
+    ///
+    /// Node credentials are not required, so the factory partition is not read. The partition
+    /// is only held by the agent.
+    pub fn builder(&self, factory_partition: NvsPartition, node_id: &str) -> RainmakerBuilder {
+        self.state().node_id = node_id.to_owned();
+        RainmakerBuilder::with_node_id(factory_partition, node_id.to_owned())
+            .transport(Box::new(self.transport()))
+    }
+
+    /// Returns a [Transport] connecting to this mock, for agents created without
+    /// [`builder`](MockCloud::builder). Only one agent can be connected at a time.
+    pub fn transport(&self) -> MockTransport {
+        MockTransport {
+            cloud: self.clone(),
+            connected: false,
+        }
+    }
+
+    /// Returns whether an agent is connected to the mock.
+    pub fn is_connected(&self) -> bool {
+        let state = self.state();
+        state.online && state.event_cb.is_some()
+    }
+
+    /// Simulates loss of the connection. Messages published by the agent are queued till
+    /// [`connect`](MockCloud::connect) is called.
+    pub fn disconnect(&self) {
+        let event_cb = {
+            let mut state = self.state();
+            state.online = false;
+            state.event_cb.clone()
+        };
+        if let Some(event_cb) = event_cb {
+            event_cb(TransportEvent::Disconnected);
+        }
+    }
+
+    /// Restores the connection after [`disconnect`](MockCloud::disconnect).
+    pub fn connect(&self) {
+        self.state().online = true;
+        self.notify_connected();
+    }
+
+    /// Returns payloads of the messages published on `node/<node_id>/<topic_suffix>`, in the
+    /// order they were published.
+    pub fn messages(&self, topic_suffix: &str) -> Vec<Vec<u8>> {
+        let state = self.state();
+        let topic = node_topic(&state.node_id, topic_suffix);
+        state
+            .published
+            .iter()
+            .filter(|(t, _)| *t == topic)
+            .map(|(_, payload)| payload.clone())
+            .collect()
+    }
+
+    /// Returns the last message published on `node/<node_id>/<topic_suffix>` parsed as JSON.
+    pub fn last_json(&self, topic_suffix: &str) -> Option<Value> {
+        self.messages(topic_suffix)
+            .last()
+            .and_then(|payload| serde_json::from_slice(payload).ok())
+    }
+
+    /// Waits for a message to be published on `node/<node_id>/<topic_suffix>` and returns the
+    /// last one, e.g. for responses published from other threads.
+    pub fn wait_for_message(&self, topic_suffix: &str, timeout: Duration) -> Option<Vec<u8>> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.state();
+        loop {
+            let topic = node_topic(&state.node_id, topic_suffix);
+            if let Some((_, payload)) = state.published.iter().rev().find(|(t, _)| *t == topic) {
+                return Some(payload.clone());
+            }
+            let remaining = deadline.checked_duration_since(Instant::now())?;
+            state = self
+                .shared
+                .published
+                .wait_timeout(state, remaining)
+                .unwrap()
+                .0;
+        }
+    }
+
+    /// Removes all the captured messages.
+    pub fn clear_messages(&self) {
+        self.state().published.clear();
+    }
+
+    /// Returns the topics(filters) subscribed by the agent.
+    pub fn subscriptions(&self) -> Vec<String> {
+        self.state().subscriptions.clone()
+    }
+
+    /// Delivers a message on `node/<node_id>/<topic_suffix>` to the agent.
+    ///
+    /// The message is handled before this returns. Returns false if the agent is not connected
+    /// or not subscribed to the topic.
+    pub fn send(&self, topic_suffix: &str, payload: Vec<u8>) -> bool {
+        let (topic, event_cb) = {
+            let state = self.state();
+            let topic = node_topic(&state.node_id, topic_suffix);
+            let subscribed = state
+                .subscriptions
+                .iter()
+                .any(|filter| topic_matches(filter, &topic));
+            if !state.online || !subscribed {
+                return false;
+            }
+            match state.event_cb.clone() {
+                Some(event_cb) => (topic, event_cb),
+                None => return false,
+            }
+        };
+
+        event_cb(TransportEvent::Received { topic, payload });
+        true
+    }
+
+    /// Writes parameter values from the cloud, i.e. sends `{"device": {"param": value}}` on
+    /// `params/remote` topic. See [`send`](MockCloud::send).
+    pub fn write_params(&self, params: Value) -> bool {
+        self.send(
+            NODE_PARAMS_REMOTE_TOPIC_SUFFIX,
+            params.to_string().into_bytes(),
+        )
+    }
+
+    // called without holding the lock, as the agent publishes on connection
+    fn notify_connected(&self) {
+        let event_cb = {
+            let state = self.state();
+            match state.online {
+                true => state.event_cb.clone(),
+                false => None,
+            }
+        };
+        if let Some(event_cb) = event_cb {
+            event_cb(TransportEvent::Connected);
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        self.shared.state.lock().unwrap()
+    }
+}
+
+/// [Transport] connecting an agent to a [MockCloud], see [`MockCloud::transport`].
+///
+/// The agent is connected as soon as it is started if the mock is online. Messages are delivered
+/// synchronously, irrespective of QoS.
+pub struct MockTransport {
+    cloud: MockCloud,
+    connected: bool,
+}
+
+impl Debug for MockTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockTransport")
+            .field("connected", &self.connected)
+            .finish()
+    }
+}
+
+impl Transport for MockTransport {
+    fn connect(
+        &mut self,
+        _factory_partition: &NvsPartition,
+        event_cb: TransportEventCb,
+    ) -> Result<(), RmakerMqttError> {
+        if self.connected {
+            return Err(RmakerMqttError::AlreadyInitialized);
+        }
+        {
+            let mut state = self.cloud.state();
+            state.subscriptions.clear();
+            state.event_cb = Some(Arc::new(event_cb));
+        }
+        self.connected = true;
+        self.cloud.notify_connected();
+
+        Ok(())
+    }
+
+    fn publish(&mut self, topic: &str, _qos: QoS, payload: Vec<u8>) -> Result<(), RmakerMqttError> {
+        self.cloud
+            .state()
+            .published
+            .push((topic.to_owned(), payload));
+        self.cloud.shared.published.notify_all();
+        Ok(())
+    }
+
+    fn subscribe(&mut self, topic: &str, _qos: QoS) -> Result<(), RmakerMqttError> {
+        let mut state = self.cloud.state();
+        if !state.subscriptions.iter().any(|t| t == topic) {
+            state.subscriptions.push(topic.to_owned());
+        }
+        Ok(())
+    }
+
+    fn unsubscribe(&mut self, topic: &str) -> Result<(), RmakerMqttError> {
+        self.cloud.state().subscriptions.retain(|t| t != topic);
+        Ok(())
+    }
+
+    fn disconnect(&mut self) {
+        if !std::mem::take(&mut self.connected) {
+            return;
+        }
+        let mut state = self.cloud.state();
+        state.event_cb = None;
+        state.subscriptions.clear();
+    }
+}
+
+impl Drop for MockTransport {
+    fn drop(&mut self) {
+        self.disconnect();
+    }
+}
+
+/// Sends a user mapping request to the agent, same as the phone application does during
+/// provisioning. The agent then publishes the mapping on `user/mapping` topic.
+///
+/// Returns true if the request is accepted.
+pub fn send_user_mapping(rmaker: &Rainmaker, user_id: &str, secret_key: &str) -> bool {
+    let req_proto = RMakerConfigPayload {
+        msg: RMakerConfigMsgType::TypeCmdSetUserMapping,
+        payload: mod_RMakerConfigPayload::OneOfpayload::cmd_set_user_mapping(CmdSetUserMapping {
+            UserID: user_id.to_owned(),
+            SecretKey: secret_key.to_owned(),
+        }),
+    };
+    let mut req = vec![];
+    if req_proto.write_message(&mut Writer::new(&mut req)).is_err() {
+        return false;
+    }
+
+    let res = cloud_user_assoc_callback(
+        "cloud_user_assoc",
+        &req,
+        &rmaker.node_id,
+        &rmaker.mqtt,
+        &rmaker.factory_partition,
+    );
+    match RMakerConfigPayload::try_from(res.as_slice()) {
+        Ok(res) => matches!(
+            res.payload,
+            mod_RMakerConfigPayload::OneOfpayload::resp_set_user_mapping(RespSetUserMapping {
+                Status: RMakerConfigStatus::Success,
+                ..
+            })
+        ),
+        Err(_) => false,
+    }
+}
+
+fn node_topic(node_id: &str, topic_suffix: &str) -> String {
+    format!("node/{}/{}", node_id, topic_suffix)
+}
@@ -0,0 +1,305 @@
+//! Time service.
+//!
+//! Adds the standard `esp.service.time` service to the node which lets the phone application set
+//! the timezone of the node. The timezone is stored in NVS and applied every time the agent
+//! starts. On ESP, system time is synchronized over SNTP. On Linux, it is expected to be managed
+//! by the OS.
+//!
+//! The timezone can be set either as a location name(`TZ` parameter, e.g. `Asia/Kolkata`) or as
+//! a POSIX TZ string(`TZ-POSIX` parameter, e.g. `IST-5:30`). When a location name is received,
+//! the corresponding POSIX string is reported back if it is known.
+//!
+//! Example:
+//! ```rust
+//! let mut rmaker = RainmakerBuilder::new(factory_partition)?
+//!     .node(node)
+//!     .time_service()
+//!     .build()?;
+//! rmaker.start()?;
+//!
+//! if rainmaker::time::is_synced() {
+//!     log::info!("local time: {:?}", rainmaker::time::local_now());
+//! }
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+use crate::{
+    device::ParamWriteResult,
+    error::RmakerTimeError,
+    param::{Param, ParamProperty, ParamTypes, ParamUi, ParamValue},
+    service::{Service, ServiceType},
+    utils::get_nvs_namespace,
+};
+
+const TIME_SERVICE_NAME: &str = "Time";
+const TIME_PARAM_TZ: &str = "TZ";
+const TIME_PARAM_TZ_POSIX: &str = "TZ-POSIX";
+const TIME_NVS_NAMESPACE: &str = "rmaker_time";
+const TIME_NVS_KEY_TZ: &str = "tz";
+const TIME_NVS_KEY_TZ_POSIX: &str = "tz_posix";
+
+// time before this is considered to be not synced
+const TIME_SYNC_MIN_YEAR: i32 = 2020;
+
+// not exposed by libc crate, available in both glibc and newlib
+extern "C" {
+    fn tzset();
+}
+
+// TZ is read by libc on every localtime_r call and is updated from the MQTT task, while it is
+// read by the scheduler. setenv concurrent with getenv is undefined behaviour, hence all the
+// accesses of the crate are serialized using this lock.
+static TZ_LOCK: Mutex<()> = Mutex::new(());
+
+/// Broken down local time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalTime {
+    pub year: i32,
+    /// 1 to 12
+    pub month: u8,
+    /// 1 to 31
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    /// Days since Monday, 0 to 6
+    pub weekday: u8,
+}
+
+impl LocalTime {
+    /// Minutes since midnight.
+    pub fn minute_of_day(&self) -> u16 {
+        self.hour as u16 * 60 + self.minute as u16
+    }
+}
+
+/// Returns true if system time has been set.
+pub fn is_synced() -> bool {
+    local_now().is_some()
+}
+
+/// Returns current local time as per the configured timezone.
+///
+/// Returns `None` if the system time is not synced yet.
+pub fn local_now() -> Option<LocalTime> {
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    {
+        let _tz = TZ_LOCK.lock().unwrap();
+        let now = unsafe { libc::time(std::ptr::null_mut()) };
+        if unsafe { libc::localtime_r(&now, &mut tm) }.is_null() {
+            return None;
+        }
+    }
+
+    if tm.tm_year + 1900 < TIME_SYNC_MIN_YEAR {
+        return None;
+    }
+
+    Some(LocalTime {
+        year: tm.tm_year + 1900,
+        month: (tm.tm_mon + 1) as u8,
+        day: tm.tm_mday as u8,
+        hour: tm.tm_hour as u8,
+        minute: tm.tm_min as u8,
+        second: tm.tm_sec as u8,
+        weekday: ((tm.tm_wday + 6) % 7) as u8,
+    })
+}
+
+/// Sets the timezone of the node and stores it in NVS.
+///
+/// `tz` can either be a location name(e.g. `Europe/Berlin`) or a POSIX TZ string(e.g.
+/// `CET-1CEST,M3.5.0,M10.5.0/3`). Returns the POSIX TZ string which was applied.
+pub fn set_timezone(tz: &str) -> Result<String, RmakerTimeError> {
+    let tz_posix = match tz_db_lookup(tz) {
+        Some(tz_posix) => tz_posix.to_string(),
+        // on linux location names are resolved by libc using the system tz database
+        None if cfg!(target_os = "linux") || !tz.contains('/') => tz.to_string(),
+        None => return Err(RmakerTimeError::UnknownTimezone(tz.to_string())),
+    };
+
+    apply_tz(&tz_posix);
+    store_tz(TIME_NVS_KEY_TZ, tz);
+    store_tz(TIME_NVS_KEY_TZ_POSIX, &tz_posix);
+    log::info!("timezone set to {}({})", tz, tz_posix);
+
+    Ok(tz_posix)
+}
+
+/// Applies the stored timezone and returns the time service to be added to the node.
+pub(crate) fn init() -> Service {
+    let tz = load_tz(TIME_NVS_KEY_TZ).unwrap_or_default();
+    let tz_posix = load_tz(TIME_NVS_KEY_TZ_POSIX).unwrap_or_default();
+    if !tz_posix.is_empty() {
+        apply_tz(&tz_posix);
+    }
+
+    let mut time_service = Service::new(TIME_SERVICE_NAME, ServiceType::Time);
+    time_service.add_param(Param::new(
+        TIME_PARAM_TZ,
+        ParamValue::String(tz),
+        ParamTypes::Timezone,
+        HashSet::from([ParamProperty::Read, ParamProperty::Write]),
+        ParamUi::Hidden,
+    ));
+    time_service.add_param(Param::new(
+        TIME_PARAM_TZ_POSIX,
+        ParamValue::String(tz_posix),
+        ParamTypes::TimezonePOSIX,
+        HashSet::from([ParamProperty::Read, ParamProperty::Write]),
+        ParamUi::Hidden,
+    ));
+    time_service.register_callback(Box::new(time_service_cb));
+
+    time_service
+}
+
+/// Starts time synchronization.
+pub(crate) fn start() -> Result<(), RmakerTimeError> {
+    #[cfg(target_os = "espidf")]
+    esp::start_sntp()?;
+
+    Ok(())
+}
+
+fn time_service_cb(params: HashMap<String, ParamValue>) -> ParamWriteResult {
+    let mut result = ParamWriteResult::new();
+
+    // POSIX string takes precedence if both are received
+    if let Some(ParamValue::String(tz_posix)) = params.get(TIME_PARAM_TZ_POSIX) {
+        apply_tz(tz_posix);
+        store_tz(TIME_NVS_KEY_TZ_POSIX, tz_posix);
+        result.accept(TIME_PARAM_TZ_POSIX, ParamValue::String(tz_posix.to_owned()));
+    } else if let Some(ParamValue::String(tz)) = params.get(TIME_PARAM_TZ) {
+        match set_timezone(tz) {
+            Ok(tz_posix) => {
+                result.accept(TIME_PARAM_TZ, ParamValue::String(tz.to_owned()));
+                result.accept(TIME_PARAM_TZ_POSIX, ParamValue::String(tz_posix));
+            }
+            Err(e) => {
+                log::error!("could not set timezone: {}", e);
+                result.reject(TIME_PARAM_TZ);
+            }
+        }
+    }
+
+    result
+}
+
+// TZ must not be modified anywhere else, see TZ_LOCK
+fn apply_tz(tz_posix: &str) {
+    let _tz = TZ_LOCK.lock().unwrap();
+    std::env::set_var("TZ", tz_posix);
+    unsafe { tzset() };
+}
+
+fn load_tz(key: &str) -> Option<String> {
+    let nvs = get_nvs_namespace(TIME_NVS_NAMESPACE)?;
+    let mut buff = vec![0u8; 64];
+
+    match nvs.get_bytes(key, &mut buff) {
+        Ok(Some(bytes)) => String::from_utf8(bytes).ok(),
+        _ => None,
+    }
+}
+
+fn store_tz(key: &str, value: &str) {
+    if let Some(mut nvs) = get_nvs_namespace(TIME_NVS_NAMESPACE) {
+        if nvs.set_bytes(key, value.as_bytes()).is_err() {
+            log::error!("could not store timezone");
+        }
+    }
+}
+
+// subset of the timezone database used by the C SDK, for targets without a system tz database
+fn tz_db_lookup(tz: &str) -> Option<&'static str> {
+    const TZ_DB: &[(&str, &str)] = &[
+        ("Africa/Cairo", "EET-2EEST,M4.5.5/0,M10.5.4/24"),
+        ("Africa/Johannesburg", "SAST-2"),
+        ("Africa/Lagos", "WAT-1"),
+        ("Africa/Nairobi", "EAT-3"),
+        ("America/Anchorage", "AKST9AKDT,M3.2.0,M11.1.0"),
+        ("America/Argentina/Buenos_Aires", "<-03>3"),
+        ("America/Bogota", "<-05>5"),
+        ("America/Chicago", "CST6CDT,M3.2.0,M11.1.0"),
+        ("America/Denver", "MST7MDT,M3.2.0,M11.1.0"),
+        ("America/Halifax", "AST4ADT,M3.2.0,M11.1.0"),
+        ("America/Los_Angeles", "PST8PDT,M3.2.0,M11.1.0"),
+        ("America/Mexico_City", "CST6"),
+        ("America/New_York", "EST5EDT,M3.2.0,M11.1.0"),
+        ("America/Phoenix", "MST7"),
+        ("America/Sao_Paulo", "<-03>3"),
+        ("America/Toronto", "EST5EDT,M3.2.0,M11.1.0"),
+        ("America/Vancouver", "PST8PDT,M3.2.0,M11.1.0"),
+        ("Asia/Bangkok", "<+07>-7"),
+        ("Asia/Dhaka", "<+06>-6"),
+        ("Asia/Dubai", "<+04>-4"),
+        ("Asia/Hong_Kong", "HKT-8"),
+        ("Asia/Jakarta", "WIB-7"),
+        ("Asia/Jerusalem", "IST-2IDT,M3.4.4/26,M10.5.0"),
+        ("Asia/Karachi", "PKT-5"),
+        ("Asia/Kathmandu", "<+0545>-5:45"),
+        ("Asia/Kolkata", "IST-5:30"),
+        ("Asia/Manila", "PST-8"),
+        ("Asia/Riyadh", "<+03>-3"),
+        ("Asia/Seoul", "KST-9"),
+        ("Asia/Shanghai", "CST-8"),
+        ("Asia/Singapore", "<+08>-8"),
+        ("Asia/Taipei", "CST-8"),
+        ("Asia/Tehran", "<+0330>-3:30"),
+        ("Asia/Tokyo", "JST-9"),
+        ("Atlantic/Reykjavik", "GMT0"),
+        ("Australia/Adelaide", "ACST-9:30ACDT,M10.1.0,M4.1.0/3"),
+        ("Australia/Brisbane", "AEST-10"),
+        ("Australia/Perth", "AWST-8"),
+        ("Australia/Sydney", "AEST-10AEDT,M10.1.0,M4.1.0/3"),
+        ("Europe/Amsterdam", "CET-1CEST,M3.5.0,M10.5.0/3"),
+        ("Europe/Athens", "EET-2EEST,M3.5.0/3,M10.5.0/4"),
+        ("Europe/Berlin", "CET-1CEST,M3.5.0,M10.5.0/3"),
+        ("Europe/Dublin", "IST-1GMT0,M10.5.0,M3.5.0/1"),
+        ("Europe/Istanbul", "<+03>-3"),
+        ("Europe/Lisbon", "WET0WEST,M3.5.0/1,M10.5.0"),
+        ("Europe/London", "GMT0BST,M3.5.0/1,M10.5.0"),
+        ("Europe/Madrid", "CET-1CEST,M3.5.0,M10.5.0/3"),
+        ("Europe/Moscow", "MSK-3"),
+        ("Europe/Paris", "CET-1CEST,M3.5.0,M10.5.0/3"),
+        ("Europe/Prague", "CET-1CEST,M3.5.0,M10.5.0/3"),
+        ("Europe/Rome", "CET-1CEST,M3.5.0,M10.5.0/3"),
+        ("Europe/Stockholm", "CET-1CEST,M3.5.0,M10.5.0/3"),
+        ("Europe/Warsaw", "CET-1CEST,M3.5.0,M10.5.0/3"),
+        ("Europe/Zurich", "CET-1CEST,M3.5.0,M10.5.0/3"),
+        ("Pacific/Auckland", "NZST-12NZDT,M9.5.0,M4.1.0/3"),
+        ("Pacific/Honolulu", "HST10"),
+        ("UTC", "UTC0"),
+    ];
+
+    TZ_DB
+        .iter()
+        .find(|(name, _)| *name == tz)
+        .map(|(_, tz_posix)| *tz_posix)
+}
+
+#[cfg(target_os = "espidf")]
+mod esp {
+    use std::sync::Mutex;
+
+    use esp_idf_svc::sntp::EspSntp;
+
+    use crate::error::RmakerTimeError;
+
+    static SNTP: Mutex<Option<EspSntp<'static>>> = Mutex::new(None);
+
+    pub(super) fn start_sntp() -> Result<(), RmakerTimeError> {
+        let mut sntp = SNTP.lock().unwrap();
+        if sntp.is_none() {
+            *sntp = Some(EspSntp::new_default().map_err(|_| RmakerTimeError::SntpError)?);
+            log::info!("SNTP started");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,259 @@
+//! Connection to the RainMaker cloud.
+//!
+//! The agent talks to the cloud through a [Transport], which connects to the MQTT broker,
+//! publishes and subscribes to the topics and reports the connection events and received
+//! messages. Everything above it(offline queue, restoring subscriptions on reconnection,
+//! dispatching messages to the topic handlers, etc.) is handled by the agent.
+//!
+//! [MqttTransport], using the MQTT client of `rainmaker_components`, is used by default. Other
+//! implementations, e.g. using a different broker library on Linux or an in-memory broker for
+//! tests(see [`test_util`](crate::test_util)), can be set using
+//! [`RainmakerBuilder::transport`](crate::RainmakerBuilder::transport).
+//!
+//! Example:
+//! ```rust
+//! let mut rmaker = RainmakerBuilder::new(factory_partition)?
+//!     .node(node)
+//!     .transport(Box::new(MyTransport::new()))
+//!     .build()?;
+//! rmaker.start()?;
+//! ```
+
+use rainmaker_components::{
+    mqtt::{MqttClient, MqttConfiguration, MqttEvent, QoSLevel, TLSconfiguration},
+    persistent_storage::NvsPartition,
+};
+
+use crate::{error::RmakerMqttError, factory};
+
+const DEFAULT_MQTT_HOST: &str = "a1p72mufdu6064-ats.iot.us-east-1.amazonaws.com";
+const DEFAULT_MQTT_PORT: u16 = 8883;
+
+/// Callback receiving the [TransportEvent]s of a connection.
+pub type TransportEventCb = Box<dyn Fn(TransportEvent) + Send + Sync + 'static>;
+
+/// MQTT quality of service level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QoS {
+    AtMostOnce,
+    AtLeastOnce,
+    ExactlyOnce,
+}
+
+/// Events reported by a [Transport] to the agent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransportEvent {
+    /// Connected to the broker. Reported again after every reconnection.
+    Connected,
+    /// Connection to the broker is lost. The transport is expected to keep retrying till it is
+    /// disconnected by the agent.
+    Disconnected,
+    /// Message received on a subscribed topic.
+    Received { topic: String, payload: Vec<u8> },
+}
+
+/// Connection used by the agent for communicating with the RainMaker cloud.
+///
+/// Methods are never called concurrently. Events may be reported from any thread, including from
+/// within [`connect`](Transport::connect). The agent restores the subscriptions on every
+/// [`TransportEvent::Connected`], so the transport does not have to.
+pub trait Transport: Send + 'static {
+    /// Starts connecting to the broker using the node credentials stored in the factory
+    /// partition. Events are reported to `event_cb` till [`disconnect`](Transport::disconnect)
+    /// is called.
+    fn connect(
+        &mut self,
+        factory_partition: &NvsPartition,
+        event_cb: TransportEventCb,
+    ) -> Result<(), RmakerMqttError>;
+    /// Publishes a message. Called only while connected.
+    fn publish(&mut self, topic: &str, qos: QoS, payload: Vec<u8>) -> Result<(), RmakerMqttError>;
+    /// Subscribes to the topic filter, which may contain `+` and `#` wildcards. Called only
+    /// while connected.
+    fn subscribe(&mut self, topic: &str, qos: QoS) -> Result<(), RmakerMqttError>;
+    /// Unsubscribes from the topic filter. Called only while connected.
+    ///
+    /// Default implementation returns [`RmakerMqttError::NotSupported`], for transports which
+    /// cannot unsubscribe.
+    fn unsubscribe(&mut self, _topic: &str) -> Result<(), RmakerMqttError> {
+        Err(RmakerMqttError::NotSupported)
+    }
+    /// Closes the connection and drops the event callback. The transport can be connected again
+    /// afterwards.
+    fn disconnect(&mut self);
+}
+
+/// MQTT endpoint used for connecting to the RainMaker cloud.
+///
+/// Unset fields are resolved as follows:
+/// - `host`: `mqtt_host` key in the `fctry` partition(same as the C SDK), then the public
+///   RainMaker endpoint.
+/// - `server_cert`: Amazon root CA used by the public RainMaker deployment.
+/// - `client_id`: node id.
+#[derive(Debug, Clone)]
+pub struct MqttConfig {
+    pub host: Option<String>,
+    pub port: u16,
+    /// PEM encoded CA certificate of the MQTT broker.
+    pub server_cert: Option<Vec<u8>>,
+    pub client_id: Option<String>,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            host: None,
+            port: DEFAULT_MQTT_PORT,
+            server_cert: None,
+            client_id: None,
+        }
+    }
+}
+
+/// Default [Transport] using the MQTT client of `rainmaker_components` over mutual TLS.
+///
+/// The client does not support unsubscribing, so [`Transport::unsubscribe`] returns
+/// [`RmakerMqttError::NotSupported`]. Topics are unsubscribed at the broker only on the next
+/// connection, when the remaining subscriptions are restored.
+pub struct MqttTransport {
+    config: MqttConfig,
+    client: Option<MqttClient>,
+    // client requires the TLS configuration to be 'static, so it is leaked once and reused by the
+    // subsequent connections as long as the credentials don't change
+    tls_config: Option<&'static TLSconfiguration<'static>>,
+}
+
+impl MqttTransport {
+    pub fn new(config: MqttConfig) -> Self {
+        Self {
+            config,
+            client: None,
+            tls_config: None,
+        }
+    }
+}
+
+impl Transport for MqttTransport {
+    fn connect(
+        &mut self,
+        factory_partition: &NvsPartition,
+        event_cb: TransportEventCb,
+    ) -> Result<(), RmakerMqttError> {
+        if self.client.is_some() {
+            return Err(RmakerMqttError::AlreadyInitialized);
+        }
+
+        let config = &self.config;
+        let mut buff = [0u8; 2500];
+
+        let node_id = match factory::get_node_id(factory_partition, &mut buff) {
+            Ok(node_id) => node_id,
+            Err(_) => return Err(RmakerMqttError::NodeCredentialsNotFound),
+        };
+        let mut client_cert = match factory::get_client_cert(factory_partition, &mut buff) {
+            Ok(cert) => cert,
+            Err(_) => return Err(RmakerMqttError::NodeCredentialsNotFound),
+        };
+        let mut private_key = match factory::get_client_key(factory_partition, &mut buff) {
+            Ok(key) => key,
+            Err(_) => return Err(RmakerMqttError::NodeCredentialsNotFound),
+        };
+        let mut server_cert = match &config.server_cert {
+            Some(cert) => cert.to_owned(),
+            None => Vec::from(include_bytes!("../server_certs/rmaker_mqtt_server.crt")),
+        };
+
+        let host = match &config.host {
+            Some(host) => host.to_owned(),
+            None => factory::get_mqtt_host(factory_partition, &mut buff)
+                .unwrap_or_else(|_| DEFAULT_MQTT_HOST.to_string()),
+        };
+        let client_id = config.client_id.as_ref().unwrap_or(&node_id);
+
+        client_cert.push(0);
+        private_key.push(0);
+        server_cert.push(0);
+
+        let mqtt_tls_config = match self.tls_config {
+            Some(tls_config)
+                if *tls_config.client_cert == client_cert
+                    && *tls_config.private_key == private_key
+                    && *tls_config.server_cert == server_cert =>
+            {
+                tls_config
+            }
+            _ => {
+                let tls_config: &'static TLSconfiguration<'static> =
+                    Box::leak(Box::new(TLSconfiguration {
+                        client_cert: Box::leak(Box::new(client_cert)),
+                        private_key: Box::leak(Box::new(private_key)),
+                        server_cert: Box::leak(Box::new(server_cert)),
+                    }));
+                self.tls_config = Some(tls_config);
+                tls_config
+            }
+        };
+
+        log::info!("connecting to MQTT broker {}:{}", host, config.port);
+        let mqtt_client = MqttClient::new(
+            &MqttConfiguration {
+                host: &host,
+                clientid: client_id,
+                port: config.port,
+            },
+            mqtt_tls_config,
+            Box::new(move |event| match event {
+                MqttEvent::Connected => event_cb(TransportEvent::Connected),
+                MqttEvent::Disconnected => event_cb(TransportEvent::Disconnected),
+                MqttEvent::Received(msg) => event_cb(TransportEvent::Received {
+                    topic: msg.topic,
+                    payload: msg.payload,
+                }),
+                _ => {}
+            }),
+        )
+        .map_err(|_| RmakerMqttError::OtherError)?;
+        self.client = Some(mqtt_client);
+
+        Ok(())
+    }
+
+    fn publish(&mut self, topic: &str, qos: QoS, payload: Vec<u8>) -> Result<(), RmakerMqttError> {
+        let client = self
+            .client
+            .as_mut()
+            .ok_or(RmakerMqttError::NotInitialized)?;
+        client.publish(topic, &qos.into(), payload);
+        Ok(())
+    }
+
+    fn subscribe(&mut self, topic: &str, qos: QoS) -> Result<(), RmakerMqttError> {
+        let client = self
+            .client
+            .as_mut()
+            .ok_or(RmakerMqttError::NotInitialized)?;
+        client
+            .subscribe(topic, &qos.into())
+            .map_err(|_| RmakerMqttError::OtherError)
+    }
+
+    fn unsubscribe(&mut self, topic: &str) -> Result<(), RmakerMqttError> {
+        log::warn!("MQTT client does not support unsubscribing from {}", topic);
+        Err(RmakerMqttError::NotSupported)
+    }
+
+    fn disconnect(&mut self) {
+        // dropping the client closes the connection
+        self.client = None;
+    }
+}
+
+impl From<QoS> for QoSLevel {
+    fn from(qos: QoS) -> Self {
+        match qos {
+            QoS::AtMostOnce => QoSLevel::AtMostOnce,
+            QoS::AtLeastOnce => QoSLevel::AtLeastOnce,
+            QoS::ExactlyOnce => QoSLevel::ExactlyOnce,
+        }
+    }
+}
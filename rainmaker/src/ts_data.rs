@@ -0,0 +1,225 @@
+//! Time series data.
+//!
+//! Values of parameters having the [`ParamProperty::TimeSeries`] property are stored by the
+//! RainMaker cloud along with the time at which they were reported, so that the phone application
+//! and dashboard can show their history as charts. Values are reported on `node/<node_id>/tsdata`
+//! topic in addition to the regular `params/local` topic, whenever the parameter is updated using
+//! [`Node::update_param`] or [`Rainmaker::report_params`].
+//!
+//! Time series values can be batched using [TsDataConfig] to reduce the number of messages. A
+//! batch is reported once it is full, or once its oldest value is held for
+//! [`max_delay`](TsDataConfig::max_delay), even if no more values are reported. Pending values
+//! are also reported when the agent is stopped and can be reported explicitly using
+//! [`Rainmaker::flush_ts_data`].
+//!
+//! Parameters having the [`ParamProperty::SimpleTimeSeries`] property are reported on
+//! `node/<node_id>/simple_tsdata` topic as soon as they change. This is meant for the values
+//! which change rarely.
+//!
+//! Values are timestamped using the system clock and are dropped until the time is synced.
+//!
+//! Example:
+//! ```rust
+//! let mut temperature = Param::new(
+//!     "Temperature",
+//!     ParamValue::Float(25.0),
+//!     ParamTypes::AmbientTemperature,
+//!     HashSet::from([ParamProperty::Read, ParamProperty::TimeSeries]),
+//!     ParamUi::Text,
+//! );
+//!
+//! let mut rmaker = builder
+//!     .node(node)
+//!     .ts_data(TsDataConfig {
+//!         batch_size: 10,
+//!         max_delay: Duration::from_secs(600),
+//!     })
+//!     .build()?;
+//! ```
+//!
+//! [`ParamProperty::TimeSeries`]: crate::param::ParamProperty::TimeSeries
+//! [`ParamProperty::SimpleTimeSeries`]: crate::param::ParamProperty::SimpleTimeSeries
+//! [`Node::update_param`]: crate::node::Node::update_param
+//! [`Rainmaker::report_params`]: crate::Rainmaker::report_params
+//! [`Rainmaker::flush_ts_data`]: crate::Rainmaker::flush_ts_data
+
+use std::{
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Mutex, Weak,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Value};
+
+use crate::{error::RmakerError, node::Node, param::ParamValue, time};
+
+const TS_DATA_VERSION: &str = "2021-09-13";
+
+/// Configuration for batching of time series data.
+#[derive(Debug, Clone)]
+pub struct TsDataConfig {
+    /// Number of values reported together. Values are reported right away if set to 1.
+    pub batch_size: usize,
+    /// Maximum time for which a value is held back in a batch. The batch is reported once its
+    /// oldest value is held for this long, while the agent is started.
+    pub max_delay: Duration,
+}
+
+impl Default for TsDataConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: 1,
+            max_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TsRecord {
+    name: String,
+    data_type: &'static str,
+    timestamp: u64,
+    value: Value,
+}
+
+/// Pending time series values of a node.
+#[derive(Debug, Default)]
+pub(crate) struct TsData {
+    config: TsDataConfig,
+    records: Vec<TsRecord>,
+    first_queued: Option<Instant>,
+}
+
+impl TsData {
+    pub(crate) fn new(config: TsDataConfig) -> Self {
+        Self {
+            config,
+            records: Vec::new(),
+            first_queued: None,
+        }
+    }
+
+    /// Adds a value of `<device_name>.<param_name>` to the batch.
+    pub(crate) fn add(&mut self, name: String, value: &ParamValue, timestamp: u64) {
+        self.first_queued.get_or_insert_with(Instant::now);
+        self.records.push(TsRecord {
+            name,
+            data_type: value.data_type(),
+            timestamp,
+            value: value.clone().into(),
+        });
+    }
+
+    /// Returns true if the batch should be reported.
+    pub(crate) fn is_due(&self) -> bool {
+        self.records.len() >= self.config.batch_size || self.is_expired()
+    }
+
+    /// Returns true if the oldest pending value is held for `max_delay`.
+    pub(crate) fn is_expired(&self) -> bool {
+        self.deadline().is_some_and(|t| t <= Instant::now())
+    }
+
+    /// Returns the time by which the pending values must be reported, if any.
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        self.first_queued.map(|t| t + self.config.max_delay)
+    }
+
+    /// Removes the pending values and returns the `tsdata` payload for them, if any.
+    pub(crate) fn take_payload(&mut self) -> Option<Vec<u8>> {
+        self.first_queued = None;
+        if self.records.is_empty() {
+            return None;
+        }
+
+        // values of the same parameter are grouped together, in the order they were added
+        let mut ts_data: Vec<Value> = vec![];
+        for record in self.records.drain(..) {
+            let value = json!({ "v": record.value, "t": record.timestamp });
+            match ts_data
+                .iter_mut()
+                .find(|e| e["name"] == record.name.as_str())
+            {
+                Some(entry) => {
+                    if let Some(records) = entry["records"].as_array_mut() {
+                        records.push(value);
+                    }
+                }
+                None => ts_data.push(json!({
+                    "name": record.name,
+                    "dt": record.data_type,
+                    "ow": false,
+                    "records": [value],
+                })),
+            }
+        }
+
+        let payload = json!({
+            "ts_data_version": TS_DATA_VERSION,
+            "ts_data": ts_data,
+        });
+        Some(payload.to_string().into_bytes())
+    }
+}
+
+/// Starts the thread reporting the batches held back for `max_delay`, so that they don't wait for
+/// the next value. Dropping the returned sender stops the thread.
+///
+/// The thread exits once stopped or the node is dropped.
+pub(crate) fn start_flush_task(
+    node: Weak<Mutex<Node>>,
+    max_delay: Duration,
+) -> Result<Sender<()>, RmakerError> {
+    let (tx, rx) = mpsc::channel();
+    thread::Builder::new()
+        .name("rmaker_tsdata".to_string())
+        .spawn(move || flush_task(node, max_delay, rx))
+        .map_err(|_| RmakerError::TsDataTaskSpawnFailed)?;
+
+    Ok(tx)
+}
+
+fn flush_task(node: Weak<Mutex<Node>>, max_delay: Duration, stop_rx: Receiver<()>) {
+    loop {
+        let deadline = match node.upgrade() {
+            Some(node) => node.lock().unwrap().flush_expired_ts_data(),
+            None => return,
+        };
+        // wake up when the pending values are due, values added meanwhile are due later
+        let timeout = deadline
+            .map(|t| t.saturating_duration_since(Instant::now()))
+            .filter(|t| !t.is_zero())
+            .unwrap_or(max_delay);
+
+        if stop_rx.recv_timeout(timeout) != Err(RecvTimeoutError::Timeout) {
+            log::info!("time series data task stopped");
+            return;
+        }
+    }
+}
+
+/// Returns the `simple_tsdata` payload for a value of `<device_name>.<param_name>`.
+pub(crate) fn simple_ts_payload(name: &str, value: &ParamValue, timestamp: u64) -> Vec<u8> {
+    json!({
+        "name": name,
+        "dt": value.data_type(),
+        "v": Value::from(value.clone()),
+        "t": timestamp,
+    })
+    .to_string()
+    .into_bytes()
+}
+
+/// Returns current UNIX timestamp in seconds, if the system time is synced.
+pub(crate) fn timestamp() -> Option<u64> {
+    if !time::is_synced() {
+        return None;
+    }
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
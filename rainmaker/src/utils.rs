@@ -1,9 +1,49 @@
 // utility types and functions for using throughout the crate
-use std::sync::{Arc, Mutex};
+use rainmaker_components::persistent_storage::{Nvs, NvsPartition};
+use std::sync::{Arc, Mutex, OnceLock};
 
 pub(crate) type WrappedInArcMutex<T> = Arc<Mutex<T>>;
 
+/// Partition used for storing runtime data of the agent.
+pub(crate) const NVS_PARTITION_NAME: &str = "nvs";
+
 #[allow(dead_code)]
 pub(crate) fn wrap_in_arc_mutex<T>(inp: T) -> WrappedInArcMutex<T> {
     Arc::new(Mutex::new(inp))
 }
+
+/// Opens a namespace in the `nvs` partition used for storing runtime data of the agent.
+///
+/// The partition handle is created once and shared between all the users.
+pub(crate) fn get_nvs_namespace(namespace: &str) -> Option<Nvs> {
+    static NVS_PARTITION: OnceLock<Option<NvsPartition>> = OnceLock::new();
+
+    let partition = NVS_PARTITION
+        .get_or_init(|| NvsPartition::new(NVS_PARTITION_NAME).ok())
+        .as_ref()?;
+    Nvs::new(partition.clone(), namespace).ok()
+}
+
+/// Returns the directory backing an NVS partition on Linux, i.e. `~/.config/rmaker/<partition>`.
+#[cfg(target_os = "linux")]
+pub(crate) fn nvs_partition_dir(partition: &str) -> Option<std::path::PathBuf> {
+    std::env::var_os("HOME").map(|home| {
+        std::path::Path::new(&home)
+            .join(".config/rmaker")
+            .join(partition)
+    })
+}
+
+/// Returns an NVS key for `name`, which may be longer than the 15 characters allowed for keys.
+///
+/// The key is `prefix` followed by a 48 bit hash of `name`.
+pub(crate) fn hashed_nvs_key(prefix: &str, name: &str) -> String {
+    // 64 bit FNV-1a
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in name.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    format!("{}{:012x}", prefix, hash & 0xffff_ffff_ffff)
+}
@@ -0,0 +1,330 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use rainmaker::{
+    command::{CommandResponse, UserRole},
+    components::persistent_storage::NvsPartition,
+    device::{Device, DeviceType, ParamWriteResult},
+    node::Node,
+    param::{Param, ParamValue},
+    system::SystemServiceConfig,
+    test_util::{send_user_mapping, MockCloud},
+    PublishQueueStats, Rainmaker,
+};
+use serde_json::json;
+
+const NODE_ID: &str = "test-node";
+const ECHO_CMD: u16 = 1;
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn switch_device() -> Device {
+    let mut device = Device::new("Switch", DeviceType::Switch);
+    device.add_param(Param::new_power("Power", false));
+    device.set_primary_param("Power");
+    device.register_callback(Box::new(ParamWriteResult::accept_all));
+    device
+}
+
+fn start_agent(cloud: &MockCloud) -> Rainmaker {
+    let mut node = Node::new(NODE_ID.to_string());
+    node.add_device(switch_device());
+
+    let mut rmaker = cloud
+        .builder(NvsPartition::new("fctry").unwrap(), NODE_ID)
+        .node(node)
+        .build()
+        .unwrap();
+    rmaker.start().unwrap();
+    rmaker
+}
+
+fn power(state: bool) -> HashMap<String, ParamValue> {
+    HashMap::from([("Power".to_string(), ParamValue::Bool(state))])
+}
+
+fn command_request(req_id: &str, role: u8, cmd: u16, data: &[u8]) -> Vec<u8> {
+    let mut payload = vec![];
+    for (tlv_type, value) in [
+        (1, req_id.as_bytes()),
+        (2, &[role][..]),
+        (5, &cmd.to_le_bytes()[..]),
+        (6, data),
+    ] {
+        payload.push(tlv_type);
+        payload.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        payload.extend_from_slice(value);
+    }
+    payload
+}
+
+fn parse_response(mut payload: &[u8]) -> HashMap<u8, Vec<u8>> {
+    let mut tlvs = HashMap::new();
+    while let [tlv_type, len_lo, len_hi, rest @ ..] = payload {
+        let len = u16::from_le_bytes([*len_lo, *len_hi]) as usize;
+        tlvs.insert(*tlv_type, rest[..len].to_vec());
+        payload = &rest[len..];
+    }
+    tlvs
+}
+
+#[test]
+fn start_publishes_config_and_initial_params() {
+    let cloud = MockCloud::new();
+    let rmaker = start_agent(&cloud);
+
+    assert!(rmaker.is_connected());
+    let config = cloud.last_json("config").unwrap();
+    assert_eq!(config["node_id"], NODE_ID);
+    assert_eq!(config["devices"][0]["name"], "Switch");
+    assert_eq!(
+        cloud.last_json("params/local/init"),
+        Some(json!({"Switch": {"Power": false}}))
+    );
+
+    let subscriptions = cloud.subscriptions();
+    assert!(subscriptions.contains(&format!("node/{}/params/remote", NODE_ID)));
+    assert!(subscriptions.contains(&format!("node/{}/to-node", NODE_ID)));
+}
+
+#[test]
+fn remote_write_is_applied_and_reported() {
+    let cloud = MockCloud::new();
+    let rmaker = start_agent(&cloud);
+
+    assert!(cloud.write_params(json!({"Switch": {"Power": true}})));
+    assert_eq!(
+        cloud.last_json("params/local"),
+        Some(json!({"Switch": {"Power": true}}))
+    );
+    let node = rmaker.get_node();
+    let node = node.lock().unwrap();
+    let value = node
+        .device("Switch")
+        .unwrap()
+        .param("Power")
+        .unwrap()
+        .value();
+    assert_eq!(*value, ParamValue::Bool(true));
+}
+
+#[test]
+fn invalid_remote_write_is_dropped() {
+    let cloud = MockCloud::new();
+    let _rmaker = start_agent(&cloud);
+
+    assert!(cloud.write_params(json!({"Switch": {"Power": "on"}})));
+    assert!(cloud.write_params(json!({"Unknown": {"Power": true}})));
+    assert!(cloud.messages("params/local").is_empty());
+}
+
+#[test]
+fn reported_params_are_published() {
+    let cloud = MockCloud::new();
+    let rmaker = start_agent(&cloud);
+
+    rmaker.report_params("Switch", power(true)).unwrap();
+    assert_eq!(
+        cloud.last_json("params/local"),
+        Some(json!({"Switch": {"Power": true}}))
+    );
+    assert!(rmaker.report_params("Unknown", power(true)).is_err());
+}
+
+#[test]
+fn user_mapping_is_published() {
+    let cloud = MockCloud::new();
+    let rmaker = start_agent(&cloud);
+
+    assert!(send_user_mapping(&rmaker, "user-id", "secret-key"));
+    assert_eq!(
+        cloud.last_json("user/mapping"),
+        Some(json!({
+            "node_id": NODE_ID,
+            "user_id": "user-id",
+            "secret_key": "secret-key",
+            "reset": true
+        }))
+    );
+}
+
+#[test]
+fn messages_are_queued_while_offline() {
+    let cloud = MockCloud::new();
+    let rmaker = start_agent(&cloud);
+
+    cloud.disconnect();
+    cloud.clear_messages();
+    rmaker.report_params("Switch", power(true)).unwrap();
+    rmaker.report_params("Switch", power(false)).unwrap();
+    assert!(cloud.messages("params/local").is_empty());
+    assert_eq!(rmaker.publish_queue_stats().queued, 2);
+
+    cloud.connect();
+    let published: Vec<_> = cloud
+        .messages("params/local")
+        .iter()
+        .map(|payload| serde_json::from_slice::<serde_json::Value>(payload).unwrap())
+        .collect();
+    assert_eq!(
+        published,
+        vec![
+            json!({"Switch": {"Power": true}}),
+            json!({"Switch": {"Power": false}})
+        ]
+    );
+    assert_eq!(
+        rmaker.publish_queue_stats(),
+        PublishQueueStats {
+            queued: 0,
+            published: 2,
+            coalesced: 0,
+            dropped: 0,
+        }
+    );
+}
+
+#[test]
+fn subscriptions_are_restored_after_reconnection() {
+    let cloud = MockCloud::new();
+    let _rmaker = start_agent(&cloud);
+
+    cloud.disconnect();
+    assert!(!cloud.write_params(json!({"Switch": {"Power": true}})));
+
+    cloud.connect();
+    assert!(cloud.write_params(json!({"Switch": {"Power": true}})));
+    assert_eq!(
+        cloud.last_json("params/local"),
+        Some(json!({"Switch": {"Power": true}}))
+    );
+}
+
+#[test]
+fn command_response_is_published() {
+    let cloud = MockCloud::new();
+    let rmaker = start_agent(&cloud);
+    rmaker
+        .register_command(
+            ECHO_CMD,
+            HashSet::from([UserRole::SuperAdmin, UserRole::PrimaryUser]),
+            Box::new(|req| CommandResponse::success(req.data.clone())),
+        )
+        .unwrap();
+
+    assert!(cloud.send("to-node", command_request("req-1", 1, ECHO_CMD, b"ping")));
+    let response = parse_response(
+        &cloud
+            .wait_for_message("from-node", RESPONSE_TIMEOUT)
+            .unwrap(),
+    );
+    assert_eq!(response[&1], b"req-1");
+    assert_eq!(response[&3], [0]);
+    assert_eq!(response[&5], ECHO_CMD.to_le_bytes());
+    assert_eq!(response[&6], b"ping");
+}
+
+#[test]
+fn command_is_rejected_for_other_roles_and_unknown_commands() {
+    let cloud = MockCloud::new();
+    let rmaker = start_agent(&cloud);
+    rmaker
+        .register_command(
+            ECHO_CMD,
+            HashSet::from([UserRole::SuperAdmin]),
+            Box::new(|req| CommandResponse::success(req.data.clone())),
+        )
+        .unwrap();
+
+    // secondary user
+    assert!(cloud.send("to-node", command_request("req-2", 4, ECHO_CMD, b"")));
+    let response = parse_response(
+        &cloud
+            .wait_for_message("from-node", RESPONSE_TIMEOUT)
+            .unwrap(),
+    );
+    assert_eq!(response[&1], b"req-2");
+    assert_eq!(response[&3], [3]);
+
+    cloud.clear_messages();
+    assert!(cloud.send("to-node", command_request("req-3", 1, ECHO_CMD + 1, b"")));
+    let response = parse_response(
+        &cloud
+            .wait_for_message("from-node", RESPONSE_TIMEOUT)
+            .unwrap(),
+    );
+    assert_eq!(response[&1], b"req-3");
+    assert_eq!(response[&3], [4]);
+}
+
+#[test]
+fn stopped_agent_disconnects() {
+    let cloud = MockCloud::new();
+    let mut rmaker = start_agent(&cloud);
+
+    rmaker.stop().unwrap();
+    assert!(!cloud.is_connected());
+    assert!(cloud.subscriptions().is_empty());
+
+    cloud.clear_messages();
+    rmaker.start().unwrap();
+    assert!(cloud.last_json("config").is_some());
+}
+
+#[test]
+fn removed_param_is_not_restored_when_added_again() {
+    let cloud = MockCloud::new();
+    let rmaker = start_agent(&cloud);
+    let node = rmaker.get_node();
+    let dimmer_level = || {
+        let mut param = Param::new_brightness("Level", 0);
+        param.set_persist(true);
+        param
+    };
+
+    let mut dimmer = Device::new("Removed Param Dimmer", DeviceType::Light);
+    dimmer.add_param(dimmer_level());
+    node.lock().unwrap().add_device(dimmer);
+    rmaker
+        .report_params(
+            "Removed Param Dimmer",
+            HashMap::from([("Level".to_string(), ParamValue::Integer(50))]),
+        )
+        .unwrap();
+
+    let value = {
+        let mut node = node.lock().unwrap();
+        assert!(node.remove_param("Removed Param Dimmer", "Level").is_some());
+        node.add_param("Removed Param Dimmer", dimmer_level())
+            .unwrap();
+        let device = node.remove_device("Removed Param Dimmer").unwrap();
+        device.param("Level").unwrap().value().clone()
+    };
+    assert_eq!(value, ParamValue::Integer(0));
+}
+
+#[test]
+fn param_can_be_removed_from_service() {
+    let cloud = MockCloud::new();
+    let mut node = Node::new(NODE_ID.to_string());
+    node.add_device(switch_device());
+    let rmaker = cloud
+        .builder(NvsPartition::new("fctry").unwrap(), NODE_ID)
+        .node(node)
+        .system_service(SystemServiceConfig::default())
+        .build()
+        .unwrap();
+    let node = rmaker.get_node();
+
+    assert!(node
+        .lock()
+        .unwrap()
+        .remove_param("System", "Reboot")
+        .is_some());
+    assert!(node
+        .lock()
+        .unwrap()
+        .remove_param("System", "Reboot")
+        .is_none());
+}